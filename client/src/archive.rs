@@ -0,0 +1,314 @@
+//! An off-chain archive of the elements of history accounts, kept in flat files,
+//! so that elements evicted from the ring buffer are not lost.
+//!
+//! Each history account is archived to `<dir>/<address>.archive`: a 16 byte header holding
+//! a tag and the element size, followed by records made of the number of the update that
+//! recorded an element, counting from 1, and the element itself. Records are appended
+//! in increasing update order.
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::clock::{Slot, DEFAULT_MS_PER_SLOT};
+use solana_sdk::pubkey::Pubkey;
+use account_history_program::state::AccountHistoryRaw;
+use crate::export::{elements_after, onchain_elements, NumberedElement};
+
+const ARCHIVE_TAG: [u8; 8] = *b"AHARCHV1";
+const ARCHIVE_HEADER_LEN: usize = 16;
+
+/// Maximum number of accounts fetched by a single `getMultipleAccounts` request.
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// An archived element of a history account, with the number of the update that recorded it.
+pub type ArchivedElement = NumberedElement;
+
+/// The ranges of updates, inclusive, missing from elements sorted by update,
+/// including those before the first element.
+pub fn gaps(elements: &[ArchivedElement]) -> Vec<(u64, u64)> {
+    let mut gaps = vec![];
+    let mut expected = 1;
+    for element in elements {
+        if element.update > expected {
+            gaps.push((expected, element.update - 1));
+        }
+        expected = element.update + 1;
+    }
+    gaps
+}
+
+/// Merge archived elements with the elements stored on-chain, both sorted by update.
+pub fn merge(archived: Vec<ArchivedElement>, onchain: Vec<ArchivedElement>) -> Vec<ArchivedElement> {
+    let last_archived = archived.last().map(|element| element.update).unwrap_or(0);
+    archived
+        .into_iter()
+        .chain(onchain.into_iter().filter(|element| element.update > last_archived))
+        .collect()
+}
+
+/// The last slot at which polling a history account is guaranteed to observe every element
+/// recorded from now on, before it is evicted: the elements are only lost after `capacity` more
+/// updates, which are at least `min_slot_delay` slots apart. `None` if `min_slot_delay` is zero,
+/// in which case updates in a single slot can evict any element, or if the account is being closed.
+pub fn eviction_deadline(history: &AccountHistoryRaw, slot: Slot) -> Option<Slot> {
+    let delay = history.header().min_slot_delay() as u64;
+    if delay == 0 {
+        return None;
+    }
+    // The next update records the first element not observed yet, which is
+    // evicted by the update `capacity` updates later
+    let first = history.next_update_slot()?.max(slot);
+    Some(first + history.capacity() as u64 * delay - 1)
+}
+
+/// The outcome of archiving the elements of a history account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Number of elements added to the archive.
+    pub archived: u64,
+    /// Updates, inclusive, whose elements were evicted on-chain before they could be archived.
+    pub missed: Option<(u64, u64)>,
+}
+
+/// A merged view of the archived and on-chain elements of a history account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedHistory {
+    /// Elements sorted by update, from oldest to newest.
+    pub elements: Vec<ArchivedElement>,
+    /// Updates, inclusive, whose elements are neither archived nor on-chain.
+    pub gaps: Vec<(u64, u64)>,
+}
+
+impl MergedHistory {
+    pub fn new(elements: Vec<ArchivedElement>) -> Self {
+        Self { gaps: gaps(&elements), elements }
+    }
+
+    /// The most recent element recorded at or before `slot`.
+    pub fn at_or_before_slot(&self, slot: Slot) -> Option<&ArchivedElement> {
+        let count = self.elements.partition_point(|element| element.slot() <= slot);
+        count.checked_sub(1).map(|n| &self.elements[n])
+    }
+
+    /// The oldest element recorded at or after `slot`.
+    pub fn at_or_after_slot(&self, slot: Slot) -> Option<&ArchivedElement> {
+        let count = self.elements.partition_point(|element| element.slot() < slot);
+        self.elements.get(count)
+    }
+
+    /// The elements recorded from slot `start` to slot `end` inclusive, from oldest to newest.
+    pub fn range_between_slots(&self, start: Slot, end: Slot) -> &[ArchivedElement] {
+        let from = self.elements.partition_point(|element| element.slot() < start);
+        let to = self.elements.partition_point(|element| element.slot() <= end);
+        &self.elements[from..to.max(from)]
+    }
+}
+
+/// A directory of archived history accounts.
+pub struct Archive {
+    dir: PathBuf,
+}
+
+impl Archive {
+    /// Open an archive directory, creating it if needed.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir: dir.as_ref().to_path_buf() })
+    }
+
+    fn path(&self, history_account: &Pubkey) -> PathBuf {
+        self.dir.join(format!("{}.archive", history_account))
+    }
+
+    /// Read the header of an archive file, returning the element size.
+    fn read_header(file: &mut File) -> Result<usize> {
+        let mut header = [0u8; ARCHIVE_HEADER_LEN];
+        file.read_exact(&mut header)?;
+        if header[..8] != ARCHIVE_TAG {
+            return Err(anyhow!("Not an archive file"));
+        }
+        Ok(u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize)
+    }
+
+    /// All the archived elements of a history account, sorted by update.
+    pub fn load(&self, history_account: &Pubkey) -> Result<Vec<ArchivedElement>> {
+        let path = self.path(history_account);
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let mut file = File::open(&path)?;
+        let element_size = Self::read_header(&mut file)?;
+        let mut records = vec![];
+        file.read_to_end(&mut records)?;
+        let chunks = records.chunks_exact(8 + element_size);
+        if !chunks.remainder().is_empty() {
+            return Err(anyhow!("Truncated archive file {}", path.display()));
+        }
+        Ok(chunks
+            .map(|record| ArchivedElement {
+                update: u64::from_le_bytes(record[..8].try_into().unwrap()),
+                element: record[8..].to_vec(),
+            })
+            .collect())
+    }
+
+    /// The number of the last archived update of a history account, without loading the whole archive.
+    pub fn last_update(&self, history_account: &Pubkey) -> Result<Option<u64>> {
+        let path = self.path(history_account);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut file = File::open(&path)?;
+        let element_size = Self::read_header(&mut file)?;
+        let records_len = file.metadata()?.len() as usize - ARCHIVE_HEADER_LEN;
+        if records_len.checked_rem(8 + element_size) != Some(0) {
+            return Err(anyhow!("Truncated archive file {}", path.display()));
+        }
+        if records_len == 0 {
+            return Ok(None);
+        }
+        file.seek(SeekFrom::End(-((8 + element_size) as i64)))?;
+        let mut update = [0u8; 8];
+        file.read_exact(&mut update)?;
+        Ok(Some(u64::from_le_bytes(update)))
+    }
+
+    /// Append elements, sorted by update and all newer than the archived ones.
+    pub fn append(&self, history_account: &Pubkey, elements: &[ArchivedElement]) -> Result<()> {
+        let element_size = match elements.first() {
+            Some(element) => element.element.len(),
+            None => return Ok(()),
+        };
+        let path = self.path(history_account);
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(&path)?;
+        if file.metadata()?.len() == 0 {
+            let mut header = [0u8; ARCHIVE_HEADER_LEN];
+            header[..8].copy_from_slice(&ARCHIVE_TAG);
+            header[8..12].copy_from_slice(&(element_size as u32).to_le_bytes());
+            file.write_all(&header)?;
+        } else if Self::read_header(&mut file)? != element_size {
+            return Err(anyhow!("The elements of {} do not match its archive", history_account));
+        }
+        let records: Vec<u8> = elements
+            .iter()
+            .flat_map(|element| element.update.to_le_bytes().into_iter().chain(element.element.iter().copied()))
+            .collect();
+        file.write_all(&records)?;
+        Ok(())
+    }
+
+    /// Archive the elements of a history account recorded since the last sync.
+    pub fn sync(&self, history_account: &Pubkey, history: &AccountHistoryRaw) -> Result<SyncReport> {
+        let after = self.last_update(history_account)?.unwrap_or(0);
+        let num_updates = history.num_updates() as u64;
+        if after > num_updates {
+            return Err(anyhow!(
+                "{} archived updates of {}, but the account only had {}",
+                after,
+                history_account,
+                num_updates,
+            ));
+        }
+        let (new, missed) = elements_after(history, after);
+        self.append(history_account, &new)?;
+        Ok(SyncReport { archived: new.len() as u64, missed })
+    }
+
+    /// The archived elements of a history account, merged with those still on-chain.
+    pub fn merged(&self, history_account: &Pubkey, history: Option<&AccountHistoryRaw>) -> Result<MergedHistory> {
+        let onchain = history.map(onchain_elements).unwrap_or_default();
+        Ok(MergedHistory::new(merge(self.load(history_account)?, onchain)))
+    }
+
+    /// Archive history accounts forever. Accounts are polled every `poll_interval`, or sooner
+    /// when needed to observe every element before it is evicted.
+    pub fn watch(&self, client: &RpcClient, histories: &[Pubkey], poll_interval: Duration) -> Result<()> {
+        loop {
+            let wait = match self.poll(client, histories) {
+                // Wait at least a slot, so that a deadline in the current slot does not spin
+                Ok(Some(deadline_slots)) => poll_interval.min(Duration::from_millis(deadline_slots.max(1) * DEFAULT_MS_PER_SLOT)),
+                Ok(None) => poll_interval,
+                Err(e) => {
+                    println!("Poll failed: {}", e);
+                    poll_interval
+                },
+            };
+            thread::sleep(wait);
+        }
+    }
+
+    /// Archive history accounts once. Returns the number of slots until the
+    /// earliest eviction deadline among the accounts, if any.
+    pub fn poll(&self, client: &RpcClient, histories: &[Pubkey]) -> Result<Option<u64>> {
+        let slot = client.get_slot()?;
+        let mut deadline: Option<Slot> = None;
+        for chunk in histories.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let accounts = client.get_multiple_accounts(chunk)?;
+            for (history_account, account) in chunk.iter().zip(accounts) {
+                let mut data = match account {
+                    Some(account) => account.data,
+                    None => continue,
+                };
+                let history = match AccountHistoryRaw::from_buffer(&mut data) {
+                    Ok(history) => history,
+                    Err(_) => continue,
+                };
+                match self.sync(history_account, &history) {
+                    Ok(report) => {
+                        if let Some((first, last)) = report.missed {
+                            println!("Warning: updates {} to {} of {} were evicted before being archived", first, last, history_account);
+                        }
+                        if report.archived > 0 {
+                            println!("Archived {} elements of {}", report.archived, history_account);
+                        }
+                    },
+                    Err(e) => println!("Failed to archive {}: {}", history_account, e),
+                }
+                if let Some(account_deadline) = eviction_deadline(&history, slot) {
+                    deadline = Some(deadline.map_or(account_deadline, |deadline| deadline.min(account_deadline)));
+                }
+            }
+        }
+        Ok(deadline.map(|deadline| deadline.saturating_sub(slot)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(update: u64, slot: Slot) -> ArchivedElement {
+        let mut element = vec![0u8; 16];
+        element[..8].copy_from_slice(&slot.to_le_bytes());
+        element[8..].copy_from_slice(&update.to_le_bytes());
+        ArchivedElement { update, element }
+    }
+
+    #[test]
+    fn archive() {
+        let history_account = Pubkey::new_unique();
+        let dir = std::env::temp_dir().join(format!("archive-{}", history_account));
+        let archive = Archive::open(&dir).unwrap();
+        assert_eq!(archive.last_update(&history_account).unwrap(), None);
+        archive.append(&history_account, &[element(3, 30), element(4, 40)]).unwrap();
+        archive.append(&history_account, &[element(5, 50)]).unwrap();
+        assert_eq!(archive.last_update(&history_account).unwrap(), Some(5));
+        let archived = archive.load(&history_account).unwrap();
+        assert_eq!(archived, vec![element(3, 30), element(4, 40), element(5, 50)]);
+        assert!(archive.append(&history_account, &[ArchivedElement { update: 6, element: vec![0; 8] }]).is_err());
+
+        // Elements evicted before being observed show up as gaps
+        let merged = MergedHistory::new(merge(archived, vec![element(5, 50), element(8, 80), element(9, 90)]));
+        assert_eq!(merged.gaps, vec![(1, 2), (6, 7)]);
+        assert_eq!(merged.elements.iter().map(|e| e.update).collect::<Vec<_>>(), vec![3, 4, 5, 8, 9]);
+        assert_eq!(merged.at_or_before_slot(79).unwrap().update, 5);
+        assert_eq!(merged.at_or_after_slot(51).unwrap().update, 8);
+        assert_eq!(merged.at_or_before_slot(29), None);
+        assert_eq!(merged.range_between_slots(40, 80).len(), 3);
+        assert!(merged.range_between_slots(81, 89).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
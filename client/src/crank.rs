@@ -0,0 +1,440 @@
+//! A keeper loop that updates history accounts as soon as their `min_slot_delay` has elapsed.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::thread;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_request::RpcError;
+use solana_sdk::account::Account;
+use solana_sdk::clock::{Slot, DEFAULT_MS_PER_SLOT};
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use account_history_program::errors::AccountHistoryProgramError;
+use account_history_program::state::AccountHistoryRaw;
+use crate::{update_multi_account, update_with_reward};
+
+/// Maximum number of accounts fetched by a single `getMultipleAccounts` request.
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct CrankConfig {
+    /// The history accounts to keep updated.
+    pub histories: Vec<Pubkey>,
+    /// Delay between two polls of the history accounts.
+    pub poll_interval: Duration,
+    /// Number of times a transaction is re-signed with a new blockhash after its blockhash expired.
+    pub max_retries: usize,
+    /// Token account receiving the bounties of token reward vaults.
+    pub reward_tokens: Option<Pubkey>,
+}
+
+/// Read a list of history accounts, one per line. Blank lines and `#` comments are ignored.
+pub fn read_history_list(path: &str) -> Result<Vec<Pubkey>> {
+    fs::read_to_string(path)?
+        .lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| line.parse().map_err(|_| anyhow!("Invalid history account in {}: {}", path, line)))
+        .collect()
+}
+
+/// Whether a history account can be updated by the crank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrankStatus {
+    /// An update would be recorded at the current slot.
+    Due,
+    /// The history's `min_slot_delay` has not elapsed yet.
+    Waiting { next_slot: Slot },
+    /// The history account is being closed, and refuses updates.
+    Closing,
+    /// Updates are restricted to another update authority.
+    NotUpdateAuthority,
+    /// The history account does not exist, or is not a history account.
+    Missing,
+    /// The last update failed with a program error caused by the watched accounts,
+    /// such as `DataUnchanged`, and is not retried until they change.
+    BackingOff { error_code: u32 },
+}
+
+/// Decide whether `signer` can update a history account at `slot`.
+pub fn crank_status(history: &AccountHistoryRaw, signer: &Pubkey, slot: Slot) -> CrankStatus {
+    if matches!(history.header().update_authority(), Some(authority) if authority != *signer) {
+        return CrankStatus::NotUpdateAuthority;
+    }
+    match history.next_update_slot() {
+        None => CrankStatus::Closing,
+        Some(next_slot) if next_slot > slot => CrankStatus::Waiting { next_slot },
+        Some(_) => CrankStatus::Due,
+    }
+}
+
+/// Metrics of the updates of a single history account.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CrankStats {
+    /// Number of confirmed updates.
+    pub updates: u64,
+    /// Number of updates that failed, after retries.
+    pub failures: u64,
+    /// Number of transactions re-sent after their blockhash expired.
+    pub retries: u64,
+    /// Slot at which the most recent confirmed update was sent.
+    pub last_update_slot: Option<Slot>,
+    /// The most recent error.
+    pub last_error: Option<String>,
+    /// The program error code of the most recent failure, if the program rejected the update.
+    pub last_error_code: Option<u32>,
+    /// Status at the most recent poll, to only log changes.
+    pub status: Option<CrankStatus>,
+    backoff: Option<Backoff>,
+}
+
+/// A failed update that would fail again until the watched accounts change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Backoff {
+    error_code: u32,
+    /// Hash of the watched accounts when the update failed.
+    watched_hash: u64,
+}
+
+/// A history account due for an update.
+struct DueUpdate {
+    history_account: Pubkey,
+    watched_accounts: Vec<Pubkey>,
+    has_reward_vault: bool,
+}
+
+/// Updates a set of history accounts whenever they are due.
+pub struct Crank<'a> {
+    client: &'a RpcClient,
+    signer: &'a dyn Signer,
+    config: CrankConfig,
+    stats: HashMap<Pubkey, CrankStats>,
+}
+
+impl<'a> Crank<'a> {
+    pub fn new(client: &'a RpcClient, signer: &'a dyn Signer, config: CrankConfig) -> Self {
+        Self {
+            client,
+            signer,
+            config,
+            stats: HashMap::new(),
+        }
+    }
+
+    /// Metrics of each history account updated so far.
+    pub fn stats(&self) -> &HashMap<Pubkey, CrankStats> {
+        &self.stats
+    }
+
+    /// Poll the history accounts forever, updating them when they are due.
+    /// Failed updates are logged and counted, and a failed poll is retried at the next one.
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            if let Err(e) = self.poll() {
+                println!("Poll failed: {}", e);
+            }
+            thread::sleep(self.config.poll_interval);
+        }
+    }
+
+    /// Fetch the history accounts once, and update those that are due.
+    pub fn poll(&mut self) -> Result<()> {
+        let slot = self.client.get_slot()?;
+        let histories = self.config.histories.clone();
+        let mut due = vec![];
+        for (history_account, account) in histories.iter().zip(self.get_accounts(&histories)?) {
+            let mut data = account.map(|account| account.data).unwrap_or_default();
+            let history = AccountHistoryRaw::from_buffer(&mut data).ok();
+            let status = history
+                .as_ref()
+                .map(|history| crank_status(history, &self.signer.pubkey(), slot))
+                .unwrap_or(CrankStatus::Missing);
+            match (status, history) {
+                (CrankStatus::Due, Some(history)) => due.push(DueUpdate {
+                    history_account: *history_account,
+                    watched_accounts: history.watched_accounts().to_vec(),
+                    has_reward_vault: history.has_reward_vault(),
+                }),
+                _ => self.log_status(history_account, status),
+            }
+        }
+        let due = self.skip_backoffs(due)?;
+        self.update(due, slot);
+        Ok(())
+    }
+
+    /// Fetch accounts, at most `MAX_MULTIPLE_ACCOUNTS` per request.
+    fn get_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        let mut accounts = Vec::with_capacity(pubkeys.len());
+        for chunk in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            accounts.extend(self.client.get_multiple_accounts(chunk)?);
+        }
+        Ok(accounts)
+    }
+
+    /// Hash the owners and data of each list of watched accounts.
+    fn watched_hashes(&self, watched_accounts: &[&[Pubkey]]) -> Result<Vec<u64>> {
+        let pubkeys: Vec<Pubkey> = watched_accounts.concat();
+        let mut accounts = self.get_accounts(&pubkeys)?.into_iter();
+        Ok(watched_accounts
+            .iter()
+            .map(|watched_accounts| {
+                let mut hasher = DefaultHasher::new();
+                for account in accounts.by_ref().take(watched_accounts.len()) {
+                    account.map(|account| (account.owner, account.data)).hash(&mut hasher);
+                }
+                hasher.finish()
+            })
+            .collect())
+    }
+
+    /// Leave out the due histories backing off from a failed update, unless their watched accounts changed since.
+    fn skip_backoffs(&mut self, due: Vec<DueUpdate>) -> Result<Vec<DueUpdate>> {
+        let backing_off: Vec<&[Pubkey]> = due
+            .iter()
+            .filter(|update| matches!(self.stats.get(&update.history_account), Some(stats) if stats.backoff.is_some()))
+            .map(|update| update.watched_accounts.as_slice())
+            .collect();
+        let mut hashes = self.watched_hashes(&backing_off)?.into_iter();
+        let mut ready = vec![];
+        for update in due {
+            let stats = self.stats.entry(update.history_account).or_default();
+            let status = match stats.backoff {
+                Some(backoff) if hashes.next() == Some(backoff.watched_hash) => {
+                    CrankStatus::BackingOff { error_code: backoff.error_code }
+                },
+                _ => {
+                    stats.backoff = None;
+                    CrankStatus::Due
+                },
+            };
+            self.log_status(&update.history_account, status);
+            if status == CrankStatus::Due {
+                ready.push(update);
+            }
+        }
+        Ok(ready)
+    }
+
+    /// Log the histories that cannot be updated, once each time their status changes.
+    fn log_status(&mut self, history_account: &Pubkey, status: CrankStatus) {
+        let stats = self.stats.entry(*history_account).or_default();
+        if stats.status == Some(status) {
+            return;
+        }
+        match status {
+            CrankStatus::Closing => println!("Skipping {}: the account is being closed", history_account),
+            CrankStatus::NotUpdateAuthority => println!("Skipping {}: not the update authority", history_account),
+            CrankStatus::Missing => println!("Skipping {}: not a history account", history_account),
+            CrankStatus::BackingOff { error_code } => {
+                println!("Skipping {}: the last update failed with error {}, until its watched accounts change", history_account, error_code);
+            },
+            CrankStatus::Due | CrankStatus::Waiting { .. } => {},
+        }
+        // Waiting histories change status at every update, so only their kind is remembered
+        stats.status = Some(match status {
+            CrankStatus::Waiting { .. } => CrankStatus::Waiting { next_slot: 0 },
+            status => status,
+        });
+    }
+
+    /// Send the updates of the due histories, then confirm them,
+    /// re-sending those whose blockhash expired.
+    fn update(&mut self, due: Vec<DueUpdate>, slot: Slot) {
+        let mut pending = vec![];
+        for update in due {
+            let ix = if update.has_reward_vault {
+                update_with_reward(self.signer.pubkey(), update.history_account, &update.watched_accounts, self.config.reward_tokens)
+            } else {
+                update_multi_account(self.signer.pubkey(), update.history_account, &update.watched_accounts)
+            };
+            match ix {
+                Ok(ix) => pending.push((update, ix)),
+                Err(e) => println!("Skipping {}: {}", update.history_account, e),
+            }
+        }
+        let mut backoffs = vec![];
+        for attempt in 0..=self.config.max_retries {
+            if pending.is_empty() {
+                break;
+            }
+            let ixs: Vec<&Instruction> = pending.iter().map(|(_, ix)| ix).collect();
+            let results = match self.send_all(&ixs) {
+                Ok(results) => results,
+                Err(e) => pending
+                    .iter()
+                    .map(|_| Err(ClientErrorKind::Custom(e.to_string()).into()))
+                    .collect(),
+            };
+            let mut expired = vec![];
+            for ((update, ix), result) in pending.into_iter().zip(results) {
+                let stats = self.stats.entry(update.history_account).or_default();
+                match result {
+                    Err(e) if is_blockhash_expired(&e) && attempt < self.config.max_retries => {
+                        stats.retries += 1;
+                        expired.push((update, ix));
+                    },
+                    Ok(signature) => {
+                        stats.updates += 1;
+                        stats.last_update_slot = Some(slot);
+                        log_update(&update.history_account, stats, Ok(&signature));
+                    },
+                    Err(e) => {
+                        stats.failures += 1;
+                        stats.last_error = Some(e.to_string());
+                        stats.last_error_code = program_error_code(&e);
+                        log_update(&update.history_account, stats, Err(&e));
+                        if let Some(error_code) = stats.last_error_code.filter(|code| depends_on_watched_accounts(*code)) {
+                            backoffs.push((update, error_code));
+                        }
+                    },
+                }
+            }
+            pending = expired;
+        }
+        if let Err(e) = self.back_off(backoffs) {
+            println!("Failed to fetch watched accounts: {}", e);
+        }
+    }
+
+    /// Send one transaction per instruction, then wait until each is confirmed, fails, or its blockhash expires.
+    fn send_all(&self, ixs: &[&Instruction]) -> ClientResult<Vec<ClientResult<Signature>>> {
+        let blockhash = self.client.get_latest_blockhash()?;
+        let mut results: Vec<Option<ClientResult<Signature>>> = vec![];
+        let mut signatures = vec![];
+        for ix in ixs {
+            let tx = Transaction::new_signed_with_payer(
+                &[(*ix).clone()],
+                Some(&self.signer.pubkey()),
+                &[self.signer],
+                blockhash,
+            );
+            match self.client.send_transaction(&tx) {
+                Ok(signature) => {
+                    signatures.push((results.len(), signature));
+                    results.push(None);
+                },
+                Err(e) => results.push(Some(Err(e))),
+            }
+        }
+        while !signatures.is_empty() {
+            // Checked before the statuses, so that a transaction without a status has expired
+            let expired = !self.client.is_blockhash_valid(&blockhash, self.client.commitment())?;
+            let statuses = self.client
+                .get_signature_statuses(&signatures.iter().map(|(_, signature)| *signature).collect::<Vec<_>>())?
+                .value;
+            let mut unconfirmed = vec![];
+            for ((i, signature), status) in signatures.into_iter().zip(statuses) {
+                results[i] = match status {
+                    Some(status) if status.satisfies_commitment(self.client.commitment()) => Some(match status.err {
+                        None => Ok(signature),
+                        Some(e) => Err(e.into()),
+                    }),
+                    None if expired => Some(Err(TransactionError::BlockhashNotFound.into())),
+                    _ => {
+                        unconfirmed.push((i, signature));
+                        None
+                    },
+                };
+            }
+            signatures = unconfirmed;
+            if !signatures.is_empty() {
+                thread::sleep(Duration::from_millis(DEFAULT_MS_PER_SLOT / 2));
+            }
+        }
+        Ok(results.into_iter().map(Option::unwrap).collect())
+    }
+
+    /// Back off from the updates that failed because of their watched accounts, until they change.
+    fn back_off(&mut self, backoffs: Vec<(DueUpdate, u32)>) -> Result<()> {
+        let watched_accounts: Vec<&[Pubkey]> = backoffs.iter().map(|(update, _)| update.watched_accounts.as_slice()).collect();
+        let hashes = self.watched_hashes(&watched_accounts)?;
+        for ((update, error_code), watched_hash) in backoffs.iter().zip(hashes) {
+            self.stats.entry(update.history_account).or_default().backoff = Some(Backoff { error_code: *error_code, watched_hash });
+        }
+        Ok(())
+    }
+}
+
+fn log_update(history_account: &Pubkey, stats: &CrankStats, result: std::result::Result<&Signature, &ClientError>) {
+    let metrics = format!(
+        "updates={} failures={} retries={}",
+        stats.updates,
+        stats.failures,
+        stats.retries,
+    );
+    match result {
+        Ok(signature) => println!("Updated {} at slot {}: {} ({})", history_account, stats.last_update_slot.unwrap(), signature, metrics),
+        Err(e) => println!("Failed to update {}: {} ({})", history_account, e, metrics),
+    }
+}
+
+/// Whether a transaction failed because its blockhash expired before it was confirmed,
+/// in which case it can be re-signed with a new blockhash.
+fn is_blockhash_expired(error: &ClientError) -> bool {
+    if error.get_transaction_error() == Some(TransactionError::BlockhashNotFound) {
+        return true;
+    }
+    matches!(
+        error.kind(),
+        ClientErrorKind::RpcError(RpcError::ForUser(message)) if message.starts_with("unable to confirm transaction"),
+    )
+}
+
+/// The custom program error code a transaction failed with, if any.
+fn program_error_code(error: &ClientError) -> Option<u32> {
+    match error.get_transaction_error() {
+        Some(TransactionError::InstructionError(_, InstructionError::Custom(code))) => Some(code),
+        _ => None,
+    }
+}
+
+/// Whether an update that failed with `error_code` would fail again until its watched accounts change.
+fn depends_on_watched_accounts(error_code: u32) -> bool {
+    [
+        AccountHistoryProgramError::DataUnchanged,
+        AccountHistoryProgramError::DataRegionOutOfBounds,
+        AccountHistoryProgramError::WatchedAccountOwnerChanged,
+        AccountHistoryProgramError::InvalidPriceAccount,
+        AccountHistoryProgramError::PriceNotTrading,
+        AccountHistoryProgramError::StalePrice,
+    ]
+    .into_iter()
+    .any(|error| u32::from(error) == error_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_list() {
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let path = std::env::temp_dir().join(format!("histories-{}.txt", first));
+        fs::write(&path, format!("# Keeper histories\n{}\n\n  {}  # price history\n", first, second)).unwrap();
+        let path = path.to_str().unwrap();
+        assert_eq!(read_history_list(path).unwrap(), vec![first, second]);
+        fs::write(path, "not a pubkey\n").unwrap();
+        assert!(read_history_list(path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn backoff_errors() {
+        let failure = |error: AccountHistoryProgramError| {
+            ClientError::from(TransactionError::InstructionError(0, InstructionError::Custom(error.into())))
+        };
+        let unchanged = program_error_code(&failure(AccountHistoryProgramError::DataUnchanged)).unwrap();
+        assert!(depends_on_watched_accounts(unchanged));
+        let not_new_slot = program_error_code(&failure(AccountHistoryProgramError::NotNewSlot)).unwrap();
+        assert!(!depends_on_watched_accounts(not_new_slot));
+        assert_eq!(program_error_code(&TransactionError::BlockhashNotFound.into()), None);
+    }
+}
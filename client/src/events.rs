@@ -0,0 +1,178 @@
+//! Decode the events emitted by the Account History Program from transaction logs.
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use anyhow::{anyhow, Result};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::UiTransactionEncoding;
+use account_history_program::events::*;
+
+/// An event emitted by the Account History Program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistoryEvent {
+    HistoryInitialized(HistoryInitialized),
+    SnapshotPushed(SnapshotPushed),
+    CloseInitiated(CloseInitiated),
+    CloseCancelled(CloseCancelled),
+    CloseResolved(CloseResolved),
+    RewardPaid(RewardPaid),
+    RewardVaultInitialized(RewardVaultInitialized),
+    RewardsDeposited(RewardsDeposited),
+    RewardsWithdrawn(RewardsWithdrawn),
+    HistoryResized(HistoryResized),
+    CloseAuthorityChanged(CloseAuthorityChanged),
+    UpdateAuthorityChanged(UpdateAuthorityChanged),
+}
+
+impl HistoryEvent {
+    /// Decode an event from its discriminator and serialized fields,
+    /// as logged by `emit!`. Returns `None` for unknown or malformed events.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < 8 {
+            return None;
+        }
+        let (discriminator, mut fields) = data.split_at(8);
+        let fields = &mut fields;
+        let event = match <[u8; 8]>::try_from(discriminator).unwrap() {
+            HistoryInitialized::DISCRIMINATOR => Self::HistoryInitialized(AnchorDeserialize::deserialize(fields).ok()?),
+            SnapshotPushed::DISCRIMINATOR => Self::SnapshotPushed(AnchorDeserialize::deserialize(fields).ok()?),
+            CloseInitiated::DISCRIMINATOR => Self::CloseInitiated(AnchorDeserialize::deserialize(fields).ok()?),
+            CloseCancelled::DISCRIMINATOR => Self::CloseCancelled(AnchorDeserialize::deserialize(fields).ok()?),
+            CloseResolved::DISCRIMINATOR => Self::CloseResolved(AnchorDeserialize::deserialize(fields).ok()?),
+            RewardPaid::DISCRIMINATOR => Self::RewardPaid(AnchorDeserialize::deserialize(fields).ok()?),
+            RewardVaultInitialized::DISCRIMINATOR => Self::RewardVaultInitialized(AnchorDeserialize::deserialize(fields).ok()?),
+            RewardsDeposited::DISCRIMINATOR => Self::RewardsDeposited(AnchorDeserialize::deserialize(fields).ok()?),
+            RewardsWithdrawn::DISCRIMINATOR => Self::RewardsWithdrawn(AnchorDeserialize::deserialize(fields).ok()?),
+            HistoryResized::DISCRIMINATOR => Self::HistoryResized(AnchorDeserialize::deserialize(fields).ok()?),
+            CloseAuthorityChanged::DISCRIMINATOR => Self::CloseAuthorityChanged(AnchorDeserialize::deserialize(fields).ok()?),
+            UpdateAuthorityChanged::DISCRIMINATOR => Self::UpdateAuthorityChanged(AnchorDeserialize::deserialize(fields).ok()?),
+            _ => return None,
+        };
+        Some(event)
+    }
+
+    /// The history account the event is about.
+    pub fn account_history(&self) -> Pubkey {
+        match self {
+            Self::HistoryInitialized(e) => e.account_history,
+            Self::SnapshotPushed(e) => e.account_history,
+            Self::CloseInitiated(e) => e.account_history,
+            Self::CloseCancelled(e) => e.account_history,
+            Self::CloseResolved(e) => e.account_history,
+            Self::RewardPaid(e) => e.account_history,
+            Self::RewardVaultInitialized(e) => e.account_history,
+            Self::RewardsDeposited(e) => e.account_history,
+            Self::RewardsWithdrawn(e) => e.account_history,
+            Self::HistoryResized(e) => e.account_history,
+            Self::CloseAuthorityChanged(e) => e.account_history,
+            Self::UpdateAuthorityChanged(e) => e.account_history,
+        }
+    }
+}
+
+/// Decode the events emitted by the Account History Program in a transaction's log messages,
+/// in the order they were emitted, including from within CPIs.
+/// Data logged by other programs is ignored.
+pub fn parse_events(logs: &[String]) -> Vec<HistoryEvent> {
+    let program_id = account_history_program::id();
+    // The programs being invoked, innermost last
+    let mut invocations: Vec<Pubkey> = vec![];
+    let mut events = vec![];
+    for log in logs {
+        let log = log.as_str();
+        if let Some(data) = log.strip_prefix("Program data: ") {
+            if invocations.last() == Some(&program_id) {
+                if let Some(event) = base64::decode(data).ok().and_then(|data| HistoryEvent::decode(&data)) {
+                    events.push(event);
+                }
+            }
+        } else if let Some(rest) = log.strip_prefix("Program ") {
+            // Only invocations and their results start with a program id,
+            // unlike "Program log:", "Program data:" or "Program return:"
+            let mut words = rest.split(' ');
+            let program = match words.next().and_then(|word| word.parse::<Pubkey>().ok()) {
+                Some(program) => program,
+                None => continue,
+            };
+            match (words.next(), words.next()) {
+                (Some("invoke"), Some(depth)) if depth.starts_with('[') && depth.ends_with(']') => {
+                    invocations.push(program);
+                },
+                (Some("success"), None) | (Some("failed:"), _) => {
+                    invocations.pop();
+                },
+                _ => {},
+            }
+        }
+    }
+    events
+}
+
+/// Fetch a confirmed transaction, and decode the events it emitted.
+pub fn transaction_events(client: &RpcClient, signature: &Signature) -> Result<Vec<HistoryEvent>> {
+    let transaction = client.get_transaction(signature, UiTransactionEncoding::Base64)?;
+    let logs: Option<Vec<String>> = transaction.transaction.meta
+        .ok_or(anyhow!("Transaction {} has no status metadata", signature))?
+        .log_messages
+        .into();
+    Ok(parse_events(&logs.unwrap_or_default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::{AnchorSerialize, Event};
+
+    fn program_data(event: &impl Event) -> String {
+        format!("Program data: {}", base64::encode(event.data()))
+    }
+
+    #[test]
+    fn parse() {
+        let account_history = Pubkey::new_unique();
+        let pushed = SnapshotPushed {
+            account_history,
+            slot: 42,
+            index: 3,
+            num_updates: 7,
+            tombstone: false,
+        };
+        let resolved = CloseResolved {
+            account_history,
+            rent_recipient: Pubkey::new_unique(),
+            lamports: 1_000_000,
+        };
+        let program_id = account_history_program::id();
+        let caller = Pubkey::new_unique();
+        let logs: Vec<String> = [
+            format!("Program {} invoke [1]", caller),
+            format!("Program {} invoke [2]", program_id),
+            "Program log: Instruction: Update".to_string(),
+            program_data(&pushed),
+            format!("Program {} consumed 5000 of 200000 compute units", program_id),
+            format!("Program {} success", program_id),
+            // Data logged by the caller is not attributed to the Account History Program
+            program_data(&resolved),
+            format!("Program {} success", caller),
+            format!("Program {} invoke [1]", program_id),
+            // Logs that merely mention success or failure do not end the invocation
+            "Program log: success".to_string(),
+            "Program log: failed: nothing to close".to_string(),
+            program_data(&resolved),
+            format!("Program {} success", program_id),
+        ].to_vec();
+        assert_eq!(
+            parse_events(&logs),
+            vec![HistoryEvent::SnapshotPushed(pushed.clone()), HistoryEvent::CloseResolved(resolved)],
+        );
+        assert_eq!(parse_events(&logs)[0].account_history(), account_history);
+        assert_eq!(HistoryEvent::decode(&pushed.try_to_vec().unwrap()), None);
+        let deposited = RewardsDeposited {
+            account_history,
+            depositor: caller,
+            mint: None,
+            amount: 5000,
+        };
+        assert_eq!(HistoryEvent::decode(&deposited.data()), Some(HistoryEvent::RewardsDeposited(deposited)));
+    }
+}
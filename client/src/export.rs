@@ -0,0 +1,262 @@
+//! Export the elements of a history account to CSV or JSON Lines files, optionally appending
+//! only the elements recorded since the previous export, to archive more than the on-chain capacity.
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+use solana_sdk::clock::Slot;
+use account_history_program::state::{entry_slot, is_tombstone, AccountHistoryRaw, AccountHistoryRawIterator};
+use crate::element_to_json;
+use crate::inspect::{interpret_regions, region_formats, RegionFormat};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+/// Parse an export format, expressed as one of "csv" or "jsonl".
+pub fn parse_export_format(value: &str) -> Result<ExportFormat> {
+    match value {
+        "csv" => Ok(ExportFormat::Csv),
+        "jsonl" => Ok(ExportFormat::Jsonl),
+        _ => Err(anyhow!("Invalid export format, must be one of csv or jsonl")),
+    }
+}
+
+/// Read the data of a history account saved with `solana account`, either
+/// as raw bytes (`--output-file`) or as JSON (`--output json`).
+pub fn read_account_dump(path: &str) -> Result<Vec<u8>> {
+    let dump = fs::read(path)?;
+    let json: Value = match serde_json::from_slice(&dump) {
+        Ok(json) => json,
+        Err(_) => return Ok(dump),
+    };
+    json.pointer("/account/data/0")
+        .and_then(Value::as_str)
+        .and_then(|data| base64::decode(data).ok())
+        .ok_or(anyhow!("{} is not a base64 account dump", path))
+}
+
+/// An element of a history account, with the number of the update that recorded it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumberedElement {
+    pub update: u64,
+    /// The element, starting with its slot.
+    pub element: Vec<u8>,
+}
+
+impl NumberedElement {
+    pub fn slot(&self) -> Slot {
+        entry_slot(&self.element)
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        is_tombstone(&self.element)
+    }
+}
+
+/// The elements currently stored on a history account, from oldest to newest.
+pub fn onchain_elements(history: &AccountHistoryRaw) -> Vec<NumberedElement> {
+    let num_updates = history.num_updates() as u64;
+    let mut elements: Vec<_> = AccountHistoryRawIterator::from(history)
+        .enumerate()
+        .map(|(i, element)| NumberedElement { update: num_updates - i as u64, element: element.to_vec() })
+        .collect();
+    elements.reverse();
+    elements
+}
+
+/// The elements currently stored on a history account that were recorded after its `after`-th
+/// update, from oldest to newest, and the updates, inclusive, recorded after it whose elements
+/// were already evicted.
+pub fn elements_after(history: &AccountHistoryRaw, after: u64) -> (Vec<NumberedElement>, Option<(u64, u64)>) {
+    let new: Vec<_> = onchain_elements(history)
+        .into_iter()
+        .filter(|element| element.update > after)
+        .collect();
+    let missed = new.first()
+        .filter(|first| first.update > after + 1)
+        .map(|first| (after + 1, first.update - 1));
+    (new, missed)
+}
+
+/// Rows of exported elements, from oldest to newest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Export {
+    /// `update`, `slot` and `tombstone`, followed by either the fields of the
+    /// schema or one column per data region.
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    /// Number of elements recorded after the previous export, but overwritten
+    /// on-chain before this one.
+    pub missed: u64,
+}
+
+impl Export {
+    /// Collect the elements of a history account recorded after its `after`-th update, or all of them.
+    /// Each row starts with the number of the update that recorded the element, counting from 1.
+    ///
+    /// Fields are named after the schema of the account when it has one, unless region
+    /// formats are given, in which case each data region is interpreted as in `HistoryView`.
+    pub fn new(history: &AccountHistoryRaw, formats: &[RegionFormat], after: Option<u64>) -> Result<Self> {
+        let schema = history.schema();
+        let by_schema = formats.is_empty() && !schema.is_empty();
+        let formats = region_formats(formats, history.data_regions().len())?;
+        let fields: Vec<String> = if by_schema {
+            schema.iter().map(|field| field.name.clone()).collect()
+        } else {
+            (0..formats.len()).map(|i| format!("region_{}", i)).collect()
+        };
+        let num_updates = history.num_updates() as u64;
+        let after = after.unwrap_or(0);
+        if after > num_updates {
+            return Err(anyhow!("Already exported {} updates, but the account only had {}", after, num_updates));
+        }
+        let (new, missed) = elements_after(history, after);
+        let rows = new
+            .into_iter()
+            .map(|NumberedElement { update, element }| {
+                let mut row = vec![json!(update), json!(entry_slot(&element)), json!(is_tombstone(&element))];
+                if is_tombstone(&element) {
+                    row.extend(fields.iter().map(|_| Value::Null));
+                } else if by_schema {
+                    let object = element_to_json(&element, &schema);
+                    row.extend(fields.iter().map(|name| object[name].clone()));
+                } else {
+                    row.extend(interpret_regions(&element, history.data_regions(), &formats)?);
+                }
+                Ok(row)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            columns: ["update", "slot", "tombstone"].iter().map(|s| s.to_string()).chain(fields).collect(),
+            missed: missed.map_or(0, |(first, last)| last - first + 1),
+            rows,
+        })
+    }
+
+    /// Render rows as CSV lines, without the header.
+    pub fn to_csv(&self) -> String {
+        self.rows
+            .iter()
+            .map(|row| row.iter().map(csv_cell).collect::<Vec<_>>().join(",") + "\n")
+            .collect()
+    }
+
+    /// Render rows as JSON objects, one per line.
+    pub fn to_jsonl(&self) -> String {
+        self.rows
+            .iter()
+            .map(|row| {
+                let object: Map<String, Value> = self.columns.iter().cloned().zip(row.iter().cloned()).collect();
+                Value::Object(object).to_string() + "\n"
+            })
+            .collect()
+    }
+
+    fn csv_header(&self) -> String {
+        self.columns.iter().map(|column| csv_cell(&json!(column))).collect::<Vec<_>>().join(",") + "\n"
+    }
+}
+
+/// Strings are written unquoted unless they contain a separator or a quote, and null as an empty cell.
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::String(s) if s.contains([',', '"', '\n']) => format!("\"{}\"", s.replace('"', "\"\"")),
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// The number of the last update written to an export file, read from its last row.
+/// Returns `None` if the file does not exist or holds no rows.
+pub fn last_exported_update(path: &Path, format: ExportFormat) -> Result<Option<u64>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(path)?;
+    let last = match contents.lines().rev().find(|line| !line.trim().is_empty()) {
+        Some(last) => last,
+        None => return Ok(None),
+    };
+    let update = match format {
+        ExportFormat::Csv if last.starts_with("update,") => return Ok(None),
+        ExportFormat::Csv => last.split(',').next().and_then(|cell| cell.parse().ok()),
+        ExportFormat::Jsonl => serde_json::from_str::<Value>(last).ok().and_then(|row| row["update"].as_u64()),
+    };
+    update.map(Some).ok_or(anyhow!("Could not read the last exported update of {}", path.display()))
+}
+
+/// Write the elements of a history account to `path`, from oldest to newest.
+///
+/// With `append`, only the elements recorded since the last row of an existing file are added,
+/// so that repeated exports build an archive longer than the account's capacity. Otherwise the
+/// file is overwritten. Returns the rows written, including the number of missed elements,
+/// which were overwritten on-chain before they could be exported.
+pub fn export_history(
+    history: &AccountHistoryRaw,
+    formats: &[RegionFormat],
+    format: ExportFormat,
+    path: &Path,
+    append: bool,
+) -> Result<Export> {
+    let after = if append { last_exported_update(path, format)? } else { None };
+    let export = Export::new(history, formats, after)?;
+    if append && format == ExportFormat::Csv && after.is_some() {
+        let header = fs::read_to_string(path)?.lines().next().unwrap_or_default().to_string() + "\n";
+        if header != export.csv_header() {
+            return Err(anyhow!("The columns of {} do not match the history account", path.display()));
+        }
+    }
+    let mut contents = match format {
+        ExportFormat::Csv => export.to_csv(),
+        ExportFormat::Jsonl => export.to_jsonl(),
+    };
+    if format == ExportFormat::Csv && after.is_none() {
+        contents = export.csv_header() + &contents;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(after.is_some())
+        .truncate(after.is_none())
+        .open(path)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(export)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let export = Export {
+            columns: vec!["update".into(), "slot".into(), "tombstone".into(), "price".into(), "label".into()],
+            rows: vec![
+                vec![json!(4), json!(10), json!(false), json!(-5), json!("a,\"b\"")],
+                vec![json!(5), json!(12), json!(true), Value::Null, Value::Null],
+            ],
+            missed: 0,
+        };
+        assert_eq!(export.csv_header(), "update,slot,tombstone,price,label\n");
+        assert_eq!(export.to_csv(), "4,10,false,-5,\"a,\"\"b\"\"\"\n5,12,true,,\n");
+        assert_eq!(
+            export.to_jsonl().lines().next().unwrap(),
+            r#"{"label":"a,\"b\"","price":-5,"slot":10,"tombstone":false,"update":4}"#,
+        );
+
+        let path = std::env::temp_dir().join(format!("export-{}.csv", solana_sdk::pubkey::Pubkey::new_unique()));
+        assert_eq!(last_exported_update(&path, ExportFormat::Csv).unwrap(), None);
+        fs::write(&path, export.csv_header()).unwrap();
+        assert_eq!(last_exported_update(&path, ExportFormat::Csv).unwrap(), None);
+        fs::write(&path, export.csv_header() + &export.to_csv()).unwrap();
+        assert_eq!(last_exported_update(&path, ExportFormat::Csv).unwrap(), Some(5));
+        fs::write(&path, export.to_jsonl()).unwrap();
+        assert_eq!(last_exported_update(&path, ExportFormat::Jsonl).unwrap(), Some(5));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,288 @@
+//! Render the header and elements of a history account for humans, or as JSON or CSV.
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+use solana_sdk::pubkey::Pubkey;
+use account_history_program::state::{entry_slot, is_tombstone, AccountHistoryRaw, AccountHistoryRawIterator};
+use account_history_program::state::regions::Region;
+
+/// How the bytes of a data region are displayed. Numbers are little-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionFormat {
+    U64,
+    I64,
+    U128,
+    Pubkey,
+    Hex,
+}
+
+impl RegionFormat {
+    /// Interpret the bytes of a region, which must have the size of the type.
+    pub fn format(&self, bytes: &[u8]) -> Result<Value> {
+        let size = match self {
+            Self::U64 | Self::I64 => 8,
+            Self::U128 => 16,
+            Self::Pubkey => 32,
+            Self::Hex => bytes.len(),
+        };
+        if bytes.len() != size {
+            return Err(anyhow!("A region of {} bytes cannot be read as {:?}", bytes.len(), self));
+        }
+        Ok(match self {
+            Self::U64 => json!(u64::from_le_bytes(bytes.try_into().unwrap())),
+            Self::I64 => json!(i64::from_le_bytes(bytes.try_into().unwrap())),
+            Self::U128 => json!(u128::from_le_bytes(bytes.try_into().unwrap()).to_string()),
+            Self::Pubkey => json!(Pubkey::try_from(bytes).unwrap().to_string()),
+            Self::Hex => json!(bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>()),
+        })
+    }
+}
+
+/// Parse a region format, expressed as one of "u64", "i64", "u128", "pubkey" or "hex".
+pub fn parse_region_format(value: &str) -> Result<RegionFormat> {
+    match value {
+        "u64" => Ok(RegionFormat::U64),
+        "i64" => Ok(RegionFormat::I64),
+        "u128" => Ok(RegionFormat::U128),
+        "pubkey" => Ok(RegionFormat::Pubkey),
+        "hex" => Ok(RegionFormat::Hex),
+        _ => Err(anyhow!("Invalid region format, must be one of u64, i64, u128, pubkey or hex")),
+    }
+}
+
+/// Expand the formats given for the data regions of a history account: either one format
+/// per region, a single format for all regions, or nothing to read regions as hex.
+pub fn region_formats(formats: &[RegionFormat], num_regions: usize) -> Result<Vec<RegionFormat>> {
+    match formats.len() {
+        0 => Ok(vec![RegionFormat::Hex; num_regions]),
+        1 => Ok(vec![formats[0]; num_regions]),
+        n if n == num_regions => Ok(formats.to_vec()),
+        n => Err(anyhow!("Got {} region formats for {} data regions", n, num_regions)),
+    }
+}
+
+/// Interpret each data region of an element, given the regions of its history account
+/// and one format per region.
+pub fn interpret_regions(element: &[u8], regions: &[Region], formats: &[RegionFormat]) -> Result<Vec<Value>> {
+    let mut start = 8;
+    regions
+        .iter()
+        .zip(formats)
+        .map(|(region, format)| {
+            let value = format.format(&element[start..start + region.len as usize]);
+            start += region.len as usize;
+            value
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+/// Parse an output format, expressed as one of "table", "json" or "csv".
+pub fn parse_output_format(value: &str) -> Result<OutputFormat> {
+    match value {
+        "table" => Ok(OutputFormat::Table),
+        "json" => Ok(OutputFormat::Json),
+        "csv" => Ok(OutputFormat::Csv),
+        _ => Err(anyhow!("Invalid output format, must be one of table, json or csv")),
+    }
+}
+
+/// An element of a history account, with each data region interpreted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryView {
+    pub slot: u64,
+    pub tombstone: bool,
+    /// One value per data region. Empty for tombstones.
+    pub values: Vec<Value>,
+}
+
+/// The decoded header and elements of a history account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryView {
+    /// Named header fields, in display order.
+    pub header: Vec<(&'static str, Value)>,
+    /// The data regions, as (watched account index, offset, length).
+    pub regions: Vec<(u32, u32, u32)>,
+    /// The elements, from newest to oldest.
+    pub entries: Vec<EntryView>,
+}
+
+fn optional(key: Option<Pubkey>) -> Value {
+    key.map(|key| json!(key.to_string())).unwrap_or(Value::Null)
+}
+
+impl HistoryView {
+    /// Decode a history account, interpreting its data regions with `formats`.
+    /// `formats` holds either one format per region, a single format for all regions,
+    /// or nothing to display regions as hex. At most `limit` elements are kept.
+    pub fn new(history: &AccountHistoryRaw, formats: &[RegionFormat], limit: Option<usize>) -> Result<Self> {
+        let header = history.header();
+        let regions: Vec<_> = history.data_regions()
+            .iter()
+            .map(|region| (region.account_index, region.offset, region.len))
+            .collect();
+        let formats = region_formats(formats, regions.len())?;
+        let entries = AccountHistoryRawIterator::from(history)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|element| {
+                let tombstone = is_tombstone(element);
+                let values = if tombstone {
+                    vec![]
+                } else {
+                    interpret_regions(element, history.data_regions(), &formats)?
+                };
+                Ok(EntryView { slot: entry_slot(element), tombstone, values })
+            })
+            .collect::<Result<_>>()?;
+        let header = vec![
+            ("watched_accounts", json!(header.watched_accounts().iter().map(Pubkey::to_string).collect::<Vec<_>>())),
+            ("pinned_owners", json!(header.watched_account_owners().into_iter().map(optional).collect::<Vec<_>>())),
+            ("close_authority", optional(header.close_authority())),
+            ("pending_close_authority", optional(header.pending_close_authority())),
+            ("update_authority", optional(header.update_authority())),
+            ("capacity", json!(history.capacity())),
+            ("len", json!(history.len())),
+            ("num_updates", json!(history.num_updates())),
+            ("min_slot_delay", json!(header.min_slot_delay())),
+            ("min_close_delay", json!(header.min_close_delay())),
+            ("close_initiated", json!(header.close_initiated())),
+            ("shrink_policy", json!(format!("{:?}", header.shrink_policy()))),
+            ("unchanged_policy", json!(format!("{:?}", header.unchanged_policy()))),
+            ("preset", json!(format!("{:?}", header.preset()))),
+            ("has_reward_vault", json!(header.has_reward_vault())),
+        ];
+        Ok(Self { header, regions, entries })
+    }
+
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Table => self.to_table(),
+            OutputFormat::Json => serde_json::to_string_pretty(&self.to_json()).unwrap(),
+            OutputFormat::Csv => self.to_csv(),
+        }
+    }
+
+    fn columns(&self) -> Vec<String> {
+        ["slot".to_string(), "tombstone".to_string()]
+            .into_iter()
+            .chain((0..self.regions.len()).map(|i| format!("region_{}", i)))
+            .collect()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                [entry.slot.to_string(), entry.tombstone.to_string()]
+                    .into_iter()
+                    .chain(entry.values.iter().map(display))
+                    .chain(std::iter::repeat(String::new()))
+                    .take(2 + self.regions.len())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The header, regions and elements as a single JSON object.
+    pub fn to_json(&self) -> Value {
+        let header: Map<String, Value> = self.header
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        let regions: Vec<Value> = self.regions
+            .iter()
+            .map(|(account_index, offset, len)| json!({ "account_index": account_index, "offset": offset, "len": len }))
+            .collect();
+        let entries: Vec<Value> = self.entries
+            .iter()
+            .map(|entry| json!({ "slot": entry.slot, "tombstone": entry.tombstone, "values": entry.values }))
+            .collect();
+        json!({ "header": header, "regions": regions, "entries": entries })
+    }
+
+    /// The elements only, one row per element. Tombstones have empty region columns.
+    pub fn to_csv(&self) -> String {
+        [self.columns()]
+            .into_iter()
+            .chain(self.rows())
+            .map(|row| row.join(",") + "\n")
+            .collect()
+    }
+
+    /// The header and regions as a list of fields, followed by the elements in aligned columns.
+    pub fn to_table(&self) -> String {
+        let mut out = String::new();
+        let name_width = self.header.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        for (name, value) in &self.header {
+            out += format!("{:width$}  {}", name, display(value), width = name_width).trim_end();
+            out += "\n";
+        }
+        for (i, (account_index, offset, len)) in self.regions.iter().enumerate() {
+            out += &format!("region {}: account {}, bytes {}..{}\n", i, account_index, offset, offset + len);
+        }
+        out += "\n";
+        let columns = self.columns();
+        let rows = self.rows();
+        let widths: Vec<usize> = (0..columns.len())
+            .map(|i| rows.iter().filter_map(|row| row.get(i)).chain([&columns[i]]).map(String::len).max().unwrap())
+            .collect();
+        for row in [columns].iter().chain(&rows) {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            out += cells.join("  ").trim_end();
+            out += "\n";
+        }
+        out
+    }
+}
+
+/// Strings are displayed without quotes, and null as an empty cell.
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let key = Pubkey::new_unique();
+        assert_eq!(RegionFormat::U64.format(&7u64.to_le_bytes()).unwrap(), json!(7));
+        assert_eq!(RegionFormat::I64.format(&(-7i64).to_le_bytes()).unwrap(), json!(-7));
+        assert_eq!(RegionFormat::U128.format(&u128::MAX.to_le_bytes()).unwrap(), json!(u128::MAX.to_string()));
+        assert_eq!(RegionFormat::Pubkey.format(key.as_ref()).unwrap(), json!(key.to_string()));
+        assert_eq!(RegionFormat::Hex.format(&[0x0a, 0xff]).unwrap(), json!("0aff"));
+        assert!(RegionFormat::U64.format(&[0; 4]).is_err());
+
+        let view = HistoryView {
+            header: vec![("capacity", json!(2)), ("update_authority", Value::Null)],
+            regions: vec![(0, 64, 8), (0, 0, 32)],
+            entries: vec![
+                EntryView { slot: 12, tombstone: true, values: vec![] },
+                EntryView { slot: 10, tombstone: false, values: vec![json!(500), json!(key.to_string())] },
+            ],
+        };
+        assert_eq!(
+            view.to_csv(),
+            format!("slot,tombstone,region_0,region_1\n12,true,,\n10,false,500,{}\n", key),
+        );
+        assert_eq!(view.to_json()["entries"][1]["values"][0], json!(500));
+        assert_eq!(view.to_json()["header"]["update_authority"], Value::Null);
+        let table = view.to_table();
+        assert!(table.starts_with("capacity          2\nupdate_authority\nregion 0: account 0, bytes 64..72\n"));
+        assert!(table.contains(&format!("10    false      500       {}\n", key)));
+    }
+}
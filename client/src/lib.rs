@@ -1,18 +1,41 @@
+pub mod archive;
 pub mod config;
+pub mod crank;
+pub mod events;
+pub mod export;
+pub mod inspect;
 use anchor_lang::prelude::*;
 use anchor_lang::InstructionData;
 use anyhow::{anyhow, Result};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use serde_json::{json, Map, Value};
 use solana_sdk::instruction::Instruction;
-use account_history_program::state::account_history_address;
+use solana_sdk::transaction::Transaction;
+use account_history_program::instructions::{HistoryQuery, HistorySettings, QueryResult, UpdateResult};
+use account_history_program::presets::pyth::PythPriceSnapshot;
+use account_history_program::presets::token::{token_account_kind, HistoricalAccountState, HistoricalBalance, HistoricalDelegate, HistoricalDelegatedAmount, HistoricalSupply, TokenAccountKind, MINT_SUPPLY_REGION, TOKEN_BALANCE_REGION, TOKEN_DELEGATED_AMOUNT_REGION, TOKEN_DELEGATE_REGION, TOKEN_PROGRAM_ID, TOKEN_STATE_REGION};
+use account_history_program::state::reward_vault::{reward_vault_address, reward_vault_tokens_address};
+use account_history_program::state::{account_history_address, entry_slot, is_tombstone, labeled_account_history_address, AccountHistoryRaw, AccountHistoryRawIterator, MAX_LABEL_LEN};
+use account_history_program::state::schema::{FieldSchema, FieldType, HistorySchema};
+use account_history_program::state::options::{HistoryOptions, ShrinkPolicy, UnchangedPolicy};
 
 
 /// Represents a contiguous chunk of bytes.
 /// Expressed as "o:l" where o is the byte offset where the region begins,
 /// and l is the region's length.
 /// For example, 0:3 is expresses the slice `data[0..3]`.
+/// When watching multiple accounts, it can be prefixed with the index of the watched account,
+/// as in "i:o:l". For example, 1:0:3 expresses the slice `data[0..3]` on the second account.
 #[derive(Debug)]
-pub struct DataRegion(u32, u32);
+pub struct DataRegion(u8, u32, u32);
 
+impl DataRegion {
+    /// Index of the watched account that this region refers to.
+    pub fn account_index(&self) -> u8 {
+        self.0
+    }
+}
 
 impl TryFrom<&str> for DataRegion {
     type Error = anyhow::Error;
@@ -21,44 +44,293 @@ impl TryFrom<&str> for DataRegion {
         let (offset, range) = value.rsplit_once(":")
             .ok_or(anyhow!("Invalid data region,\
             must be offset:range where offset and range are positive integers"))?;
+        let (account_index, offset) = match offset.split_once(":") {
+            Some((account_index, offset)) => (
+                account_index.parse()
+                    .map_err(|_| anyhow!("invalid integer value for account index: {}", account_index))?,
+                offset,
+            ),
+            None => (0, offset),
+        };
         let offset: u32 = offset.parse()
             .map_err(|_| anyhow!("invalid integer value for offset: {}", offset))?;
         let range: u32 = range.parse()
             .map_err(|_| anyhow!("invalid integer value for range: {}", range))?;
-        Ok(Self(offset, range))
+        Ok(Self(account_index, offset, range))
     }
 }
 
 impl Into<(u32, u32)> for DataRegion {
     fn into(self) -> (u32, u32) {
-        (self.0, self.1)
+        (self.1, self.2)
+    }
+}
+
+/// Parse a shrink policy, expressed as one of "error", "tombstone" or "close".
+pub fn parse_shrink_policy(value: &str) -> Result<ShrinkPolicy> {
+    match value {
+        "error" => Ok(ShrinkPolicy::Error),
+        "tombstone" => Ok(ShrinkPolicy::Tombstone),
+        "close" => Ok(ShrinkPolicy::Close),
+        _ => Err(anyhow!("Invalid shrink policy, must be one of error, tombstone or close")),
+    }
+}
+
+/// Parse an unchanged policy, expressed as one of "record", "skip" or "reject".
+pub fn parse_unchanged_policy(value: &str) -> Result<UnchangedPolicy> {
+    match value {
+        "record" => Ok(UnchangedPolicy::Record),
+        "skip" => Ok(UnchangedPolicy::Skip),
+        "reject" => Ok(UnchangedPolicy::Reject),
+        _ => Err(anyhow!("Invalid unchanged policy, must be one of record, skip or reject")),
+    }
+}
+
+/// Parse a field of an element schema, expressed as "name:offset:type", where the offset
+/// counts the 8 bytes of the slot, and the type is one of u8, u16, u32, u64, u128,
+/// i8, i16, i32, i64, i128, bool, pubkey, or bytesN for N opaque bytes.
+/// For example, "balance:8:u64".
+pub fn parse_field_schema(value: &str) -> Result<FieldSchema> {
+    let mut parts = value.splitn(3, ':');
+    let (name, offset, field_type) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(name), Some(offset), Some(field_type)) => (name, offset, field_type),
+        _ => return Err(anyhow!("Invalid field, must be name:offset:type")),
+    };
+    let offset: u32 = offset.parse()
+        .map_err(|_| anyhow!("invalid integer value for offset: {}", offset))?;
+    let field_type = match field_type {
+        "u8" => FieldType::U8,
+        "u16" => FieldType::U16,
+        "u32" => FieldType::U32,
+        "u64" => FieldType::U64,
+        "u128" => FieldType::U128,
+        "i8" => FieldType::I8,
+        "i16" => FieldType::I16,
+        "i32" => FieldType::I32,
+        "i64" => FieldType::I64,
+        "i128" => FieldType::I128,
+        "bool" => FieldType::Bool,
+        "pubkey" => FieldType::Pubkey,
+        _ => match field_type.strip_prefix("bytes").map(str::parse) {
+            Some(Ok(len)) => FieldType::Bytes(len),
+            _ => return Err(anyhow!("Invalid field type: {}", field_type)),
+        },
+    };
+    Ok(FieldSchema::new(name, offset, field_type))
+}
+
+/// Render an element as a JSON object, with its slot and each field of the schema.
+/// Integers wider than 64 bits are rendered as strings, public keys in base58,
+/// and opaque bytes in base64. Tombstone elements only have their slot and a `tombstone` flag.
+pub fn element_to_json(element: &[u8], schema: &[FieldSchema]) -> Value {
+    let mut object = Map::new();
+    object.insert("slot".to_string(), json!(entry_slot(element)));
+    if is_tombstone(element) {
+        object.insert("tombstone".to_string(), json!(true));
+        return Value::Object(object);
+    }
+    for field in schema {
+        let bytes = &element[field.offset as usize..][..field.field_type.size() as usize];
+        let value = match field.field_type {
+            FieldType::U8 => json!(bytes[0]),
+            FieldType::U16 => json!(u16::from_le_bytes(bytes.try_into().unwrap())),
+            FieldType::U32 => json!(u32::from_le_bytes(bytes.try_into().unwrap())),
+            FieldType::U64 => json!(u64::from_le_bytes(bytes.try_into().unwrap())),
+            FieldType::U128 => json!(u128::from_le_bytes(bytes.try_into().unwrap()).to_string()),
+            FieldType::I8 => json!(bytes[0] as i8),
+            FieldType::I16 => json!(i16::from_le_bytes(bytes.try_into().unwrap())),
+            FieldType::I32 => json!(i32::from_le_bytes(bytes.try_into().unwrap())),
+            FieldType::I64 => json!(i64::from_le_bytes(bytes.try_into().unwrap())),
+            FieldType::I128 => json!(i128::from_le_bytes(bytes.try_into().unwrap()).to_string()),
+            FieldType::Bool => json!(bytes[0] != 0),
+            FieldType::Pubkey => json!(Pubkey::try_from(bytes).unwrap().to_string()),
+            FieldType::Bytes(_) => json!(base64::encode(bytes)),
+        };
+        object.insert(field.name.clone(), value);
+    }
+    Value::Object(object)
+}
+
+/// Render the elements of a history account as JSON objects, from oldest to newest,
+/// using the schema stored on the account. Fails if the account has no schema.
+pub fn history_to_json(data: &mut [u8]) -> Result<Vec<Value>> {
+    let history = AccountHistoryRaw::from_buffer(data)?;
+    let schema = history.schema();
+    if schema.is_empty() {
+        return Err(anyhow!("History account has no schema"));
+    }
+    let mut elements: Vec<Value> = AccountHistoryRawIterator::from(&history)
+        .map(|element| element_to_json(element, &schema))
+        .collect();
+    elements.reverse();
+    Ok(elements)
+}
+
+/// A well-known account type, whose data regions are configured automatically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresetKind {
+    /// A Pyth price account, see `initialize_pyth_price_history`.
+    Pyth,
+    /// The balance of an SPL Token or Token-2022 account, read as a `HistoricalBalance`.
+    TokenBalance,
+    /// The delegated amount of a token account, read as a `HistoricalDelegatedAmount`.
+    TokenDelegatedAmount,
+    /// The delegate of a token account, read as a `HistoricalDelegate`.
+    TokenDelegate,
+    /// The state of a token account, read as a `HistoricalAccountState`.
+    TokenState,
+    /// The supply of a mint, read as a `HistoricalSupply`.
+    MintSupply,
+}
+
+impl PresetKind {
+    /// The kind of token program account and the data region recorded by token presets.
+    pub fn token_region(&self) -> Option<(TokenAccountKind, (u32, u32))> {
+        match self {
+            Self::Pyth => None,
+            Self::TokenBalance => Some((TokenAccountKind::Account, TOKEN_BALANCE_REGION)),
+            Self::TokenDelegatedAmount => Some((TokenAccountKind::Account, TOKEN_DELEGATED_AMOUNT_REGION)),
+            Self::TokenDelegate => Some((TokenAccountKind::Account, TOKEN_DELEGATE_REGION)),
+            Self::TokenState => Some((TokenAccountKind::Account, TOKEN_STATE_REGION)),
+            Self::MintSupply => Some((TokenAccountKind::Mint, MINT_SUPPLY_REGION)),
+        }
+    }
+
+    /// The schema of the element type recorded by the preset.
+    pub fn schema(&self) -> Vec<FieldSchema> {
+        match self {
+            Self::Pyth => PythPriceSnapshot::schema(),
+            Self::TokenBalance => HistoricalBalance::schema(),
+            Self::TokenDelegatedAmount => HistoricalDelegatedAmount::schema(),
+            Self::TokenDelegate => HistoricalDelegate::schema(),
+            Self::TokenState => HistoricalAccountState::schema(),
+            Self::MintSupply => HistoricalSupply::schema(),
+        }
     }
 }
 
+/// Parse a preset, expressed as one of "pyth", "token-balance", "token-delegated-amount",
+/// "token-delegate", "token-state" or "mint-supply".
+pub fn parse_preset(value: &str) -> Result<PresetKind> {
+    match value {
+        "pyth" => Ok(PresetKind::Pyth),
+        "token-balance" => Ok(PresetKind::TokenBalance),
+        "token-delegated-amount" => Ok(PresetKind::TokenDelegatedAmount),
+        "token-delegate" => Ok(PresetKind::TokenDelegate),
+        "token-state" => Ok(PresetKind::TokenState),
+        "mint-supply" => Ok(PresetKind::MintSupply),
+        _ => Err(anyhow!("Invalid preset, must be one of pyth, token-balance, token-delegated-amount, token-delegate, token-state or mint-supply")),
+    }
+}
+
+/// The data regions of a token preset, after checking that the watched account
+/// is owned by SPL Token or Token-2022 and is of the kind the preset records.
+pub fn token_preset_regions(client: &RpcClient, preset: PresetKind, watched_account: &Pubkey) -> Result<Vec<(u32, u32)>> {
+    let (expected, region) = preset.token_region()
+        .ok_or(anyhow!("Not a token preset: {:?}", preset))?;
+    let account = client.get_account(watched_account)?;
+    match token_account_kind(&account.owner, &account.data) {
+        Some(kind) if kind == expected => Ok(vec![region]),
+        Some(kind) => Err(anyhow!("Expected a token {:?}, but {} is a token {:?}", expected, watched_account, kind)),
+        None => Err(anyhow!("{} is not an SPL Token or Token-2022 account, its owner is {}", watched_account, account.owner)),
+    }
+}
 
+
+/// Create a history account watching a single account. Without `options`, this sends
+/// `initialize_account_history`, whose arguments are unchanged since the first release,
+/// and `initialize_account_history_with_options` otherwise.
+#[allow(clippy::too_many_arguments)]
 pub fn initialize_account_history(
     capacity: u32,
     data_regions: Vec<(u32, u32)>,
     min_slot_delay: Option<u32>,
     min_close_delay: Option<u32>,
+    options: Option<HistoryOptions>,
     payer: Pubkey,
     seed: Pubkey,
     watched_account: Pubkey,
 ) -> Instruction {
-    let data = account_history_program::instruction::InitializeAccountHistory {
+    let history_pubkey = account_history_address(seed.to_bytes()).0;
+    let (data, accounts) = match options {
+        None => (
+            account_history_program::instruction::InitializeAccountHistory {
+                capacity,
+                data_regions,
+                min_slot_delay,
+                min_close_delay,
+            }.data(),
+            account_history_program::accounts::InitializeAccountHistory {
+                payer,
+                seed,
+                account_state_history: history_pubkey,
+                watched_account,
+                system_program: System::id(),
+            }.to_account_metas(None),
+        ),
+        Some(options) => (
+            account_history_program::instruction::InitializeAccountHistoryWithOptions {
+                capacity,
+                data_regions,
+                settings: HistorySettings {
+                    min_slot_delay,
+                    min_close_delay,
+                    update_authority: None,
+                    options,
+                },
+            }.data(),
+            account_history_program::accounts::InitializeAccountHistoryWithOptions {
+                payer,
+                seed,
+                account_state_history: history_pubkey,
+                watched_account,
+                system_program: System::id(),
+            }.to_account_metas(None),
+        ),
+    };
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+/// Create a history account watching several accounts.
+/// Each watched account is listed with its own data regions, and the element layout
+/// is the concatenation of each account's regions, in the order given.
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_multi_account_history(
+    capacity: u32,
+    watched_accounts: Vec<(Pubkey, Vec<(u32, u32)>)>,
+    min_slot_delay: Option<u32>,
+    min_close_delay: Option<u32>,
+    update_authority: Option<Pubkey>,
+    options: Option<HistoryOptions>,
+    payer: Pubkey,
+    seed: Pubkey,
+) -> Instruction {
+    let data_regions = indexed_data_regions(&watched_accounts);
+    let data = account_history_program::instruction::InitializeMultiAccountHistory {
         capacity,
         data_regions,
         min_slot_delay,
         min_close_delay,
+        update_authority,
+        options,
     }.data();
     let history_pubkey = account_history_address(seed.to_bytes()).0;
-    let accounts = account_history_program::accounts::InitializeAccountHistory {
+    let mut accounts = account_history_program::accounts::InitializeMultiAccountHistory {
         payer,
         seed,
         account_state_history: history_pubkey,
-        watched_account,
+        watched_account: watched_accounts[0].0,
         system_program: System::id(),
     }.to_account_metas(None);
+    accounts.extend(
+        watched_accounts[1..]
+            .iter()
+            .map(|(pubkey, _)| AccountMeta::new_readonly(*pubkey, false))
+    );
     Instruction {
         data,
         accounts,
@@ -66,6 +338,130 @@ pub fn initialize_account_history(
     }
 }
 
+/// Create a history account recording the aggregate price of a Pyth price account,
+/// whose elements are `PythPriceSnapshot`s. Updates are rejected unless the price is trading
+/// and was published at most `max_price_age` slots earlier. The owner of the price account is pinned.
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_pyth_price_history(
+    capacity: u32,
+    max_price_age: u32,
+    min_slot_delay: Option<u32>,
+    min_close_delay: Option<u32>,
+    update_authority: Option<Pubkey>,
+    options: Option<HistoryOptions>,
+    payer: Pubkey,
+    seed: Pubkey,
+    price_account: Pubkey,
+) -> Instruction {
+    let data = account_history_program::instruction::InitializePythPriceHistory {
+        capacity,
+        max_price_age,
+        min_slot_delay,
+        min_close_delay,
+        update_authority,
+        options,
+    }.data();
+    let accounts = account_history_program::accounts::InitializePythPriceHistory {
+        payer,
+        seed,
+        account_state_history: account_history_address(seed.to_bytes()).0,
+        price_account,
+        system_program: System::id(),
+    }.to_account_metas(None);
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+/// Create a history account at an address derived from the first watched account,
+/// the creator and a label. Watched accounts are listed as in `initialize_multi_account_history`.
+/// Fails if the label is longer than `MAX_LABEL_LEN` bytes.
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_labeled_account_history(
+    capacity: u32,
+    watched_accounts: Vec<(Pubkey, Vec<(u32, u32)>)>,
+    label: &str,
+    min_slot_delay: Option<u32>,
+    min_close_delay: Option<u32>,
+    update_authority: Option<Pubkey>,
+    options: Option<HistoryOptions>,
+    creator: Pubkey,
+) -> Result<Instruction> {
+    let history_pubkey = find_labeled_account_history_address(
+        &watched_accounts[0].0,
+        &creator,
+        label,
+    )?;
+    let data = account_history_program::instruction::InitializeLabeledAccountHistory {
+        capacity,
+        data_regions: indexed_data_regions(&watched_accounts),
+        _label: label.to_string(),
+        settings: HistorySettings {
+            min_slot_delay,
+            min_close_delay,
+            update_authority,
+            options: options.unwrap_or_default(),
+        },
+    }.data();
+    let mut accounts = account_history_program::accounts::InitializeLabeledAccountHistory {
+        creator,
+        account_state_history: history_pubkey,
+        watched_account: watched_accounts[0].0,
+        system_program: System::id(),
+    }.to_account_metas(None);
+    accounts.extend(
+        watched_accounts[1..]
+            .iter()
+            .map(|(pubkey, _)| AccountMeta::new_readonly(*pubkey, false))
+    );
+    Ok(Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    })
+}
+
+/// Flatten each watched account's data regions into (account index, offset, length) tuples.
+fn indexed_data_regions(watched_accounts: &[(Pubkey, Vec<(u32, u32)>)]) -> Vec<(u8, u32, u32)> {
+    watched_accounts
+        .iter()
+        .enumerate()
+        .flat_map(|(i, (_, regions))| {
+            regions.iter().map(move |(offset, len)| (i as u8, *offset, *len))
+        })
+        .collect()
+}
+
+/// Address of the labeled history account created by `creator` to watch `watched_account`.
+/// Fails if the label is longer than `MAX_LABEL_LEN` bytes.
+pub fn find_labeled_account_history_address(
+    watched_account: &Pubkey,
+    creator: &Pubkey,
+    label: &str,
+) -> Result<Pubkey> {
+    if label.len() > MAX_LABEL_LEN {
+        return Err(anyhow!("Label must be at most {} bytes long", MAX_LABEL_LEN));
+    }
+    Ok(labeled_account_history_address(watched_account, creator, label).0)
+}
+
+/// Look up the labeled history account created by `creator` to watch `watched_account`,
+/// returning its address and data if it exists.
+pub fn find_labeled_account_history(
+    client: &RpcClient,
+    watched_account: &Pubkey,
+    creator: &Pubkey,
+    label: &str,
+) -> Result<Option<(Pubkey, Vec<u8>)>> {
+    let address = find_labeled_account_history_address(watched_account, creator, label)?;
+    let account = client
+        .get_account_with_commitment(&address, client.commitment())?
+        .value;
+    Ok(account.map(|account| (address, account.data)))
+}
+
 pub fn update(
     signer: Pubkey,
     account_history: Pubkey,
@@ -76,6 +472,10 @@ pub fn update(
         signer,
         account_state_history: account_history,
         watched_account,
+        reward_vault: None,
+        vault_tokens: None,
+        recipient_tokens: None,
+        token_program: None,
     }.to_account_metas(None);
     Instruction {
         data,
@@ -84,6 +484,56 @@ pub fn update(
     }
 }
 
+/// Update a history account watching several accounts.
+/// The watched accounts must be in the same order as they are stored on the history account.
+pub fn update_multi_account(
+    signer: Pubkey,
+    account_history: Pubkey,
+    watched_accounts: &[Pubkey],
+) -> Result<Instruction> {
+    let (watched_account, additional_accounts) = watched_accounts
+        .split_first()
+        .ok_or_else(|| anyhow!("No watched accounts to update"))?;
+    let mut ix = update(signer, account_history, *watched_account);
+    ix.accounts.extend(
+        additional_accounts
+            .iter()
+            .map(|pubkey| AccountMeta::new_readonly(*pubkey, false))
+    );
+    Ok(ix)
+}
+
+/// Update several history accounts, each watching a single account, in one instruction.
+/// Takes (history account, watched account) pairs. A history account that cannot be updated
+/// is skipped without failing the others, and the instruction returns an `UpdateResult` per pair.
+pub fn update_many(
+    signer: Pubkey,
+    pairs: &[(Pubkey, Pubkey)],
+) -> Instruction {
+    let data = account_history_program::instruction::UpdateMany.data();
+    let mut accounts = account_history_program::accounts::UpdateMany {
+        signer,
+    }.to_account_metas(None);
+    for (account_history, watched_account) in pairs {
+        accounts.push(AccountMeta::new(*account_history, false));
+        accounts.push(AccountMeta::new_readonly(*watched_account, false));
+    }
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+/// Simulate `update_many`, returning what the result of each update would be.
+pub fn simulate_update_many(
+    client: &RpcClient,
+    signer: Pubkey,
+    pairs: &[(Pubkey, Pubkey)],
+) -> Result<Vec<UpdateResult>> {
+    simulate_return_data(client, &[update_many(signer, pairs)], signer)
+}
+
 pub fn initialize_account_close(
     close_authority: Pubkey,
     account_history: Pubkey,
@@ -100,6 +550,22 @@ pub fn initialize_account_close(
     }
 }
 
+pub fn cancel_account_close(
+    close_authority: Pubkey,
+    account_history: Pubkey,
+) -> Instruction {
+    let data = account_history_program::instruction::CancelAccountClose.data();
+    let accounts = account_history_program::accounts::CancelAccountClose {
+        close_authority,
+        account_state_history: account_history,
+    }.to_account_metas(None);
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
 pub fn resolve_account_close(
     close_authority: Pubkey,
     account_history: Pubkey,
@@ -111,6 +577,128 @@ pub fn resolve_account_close(
         close_authority,
         rent_recipient,
         account_state_history: account_history,
+        reward_vault: None,
+        vault_tokens: None,
+        recipient_tokens: None,
+        token_program: None,
+    }.to_account_metas(None);
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+/// Same as `resolve_account_close`, for a history account with a reward vault.
+/// The vault's lamports are swept to the rent recipient, and the remaining tokens
+/// of a token reward vault to `recipient_tokens`, which is required for token reward vaults.
+pub fn resolve_account_close_with_reward_vault(
+    close_authority: Pubkey,
+    account_history: Pubkey,
+    rent_recipient: Option<Pubkey>,
+    recipient_tokens: Option<Pubkey>,
+) -> Instruction {
+    let data = account_history_program::instruction::ResolveAccountClose.data();
+    let rent_recipient = rent_recipient.unwrap_or(close_authority);
+    let (vault_tokens, recipient_tokens, token_program) = reward_token_accounts(&account_history, recipient_tokens);
+    let accounts = account_history_program::accounts::ResolveAccountClose {
+        close_authority,
+        rent_recipient,
+        account_state_history: account_history,
+        reward_vault: Some(reward_vault_address(&account_history).0),
+        vault_tokens,
+        recipient_tokens,
+        token_program,
+    }.to_account_metas(None);
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+/// Attach a reward vault paying `bounty` lamports for each update of a history account
+/// that passes the vault, as `update_with_reward` does.
+pub fn initialize_reward_vault(
+    close_authority: Pubkey,
+    account_history: Pubkey,
+    bounty: u64,
+) -> Instruction {
+    let data = account_history_program::instruction::InitializeRewardVault {
+        bounty,
+    }.data();
+    let accounts = account_history_program::accounts::InitializeRewardVault {
+        close_authority,
+        account_state_history: account_history,
+        reward_vault: reward_vault_address(&account_history).0,
+        system_program: System::id(),
+    }.to_account_metas(None);
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+/// Attach a reward vault paying `bounty` SPL tokens of `mint` for each update of a history account
+/// that passes the vault, as `update_with_reward` does.
+pub fn initialize_token_reward_vault(
+    close_authority: Pubkey,
+    account_history: Pubkey,
+    mint: Pubkey,
+    bounty: u64,
+) -> Instruction {
+    let data = account_history_program::instruction::InitializeTokenRewardVault {
+        bounty,
+    }.data();
+    let accounts = account_history_program::accounts::InitializeTokenRewardVault {
+        close_authority,
+        account_state_history: account_history,
+        reward_vault: reward_vault_address(&account_history).0,
+        mint,
+        vault_tokens: reward_vault_tokens_address(&account_history).0,
+        token_program: TOKEN_PROGRAM_ID,
+        system_program: System::id(),
+    }.to_account_metas(None);
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+/// The token accounts passed for a token reward vault, when a token account
+/// to send tokens to or take tokens from is given.
+fn reward_token_accounts(account_history: &Pubkey, tokens: Option<Pubkey>) -> (Option<Pubkey>, Option<Pubkey>, Option<Pubkey>) {
+    match tokens {
+        Some(tokens) => (
+            Some(reward_vault_tokens_address(account_history).0),
+            Some(tokens),
+            Some(TOKEN_PROGRAM_ID),
+        ),
+        None => (None, None, None),
+    }
+}
+
+/// Add rewards to the reward vault of a history account. Token rewards are taken
+/// from `depositor_tokens`, which is required for token reward vaults.
+pub fn deposit_rewards(
+    depositor: Pubkey,
+    account_history: Pubkey,
+    amount: u64,
+    depositor_tokens: Option<Pubkey>,
+) -> Instruction {
+    let data = account_history_program::instruction::DepositRewards {
+        amount,
+    }.data();
+    let (vault_tokens, depositor_tokens, token_program) = reward_token_accounts(&account_history, depositor_tokens);
+    let accounts = account_history_program::accounts::DepositRewards {
+        depositor,
+        reward_vault: reward_vault_address(&account_history).0,
+        vault_tokens,
+        depositor_tokens,
+        token_program,
+        system_program: System::id(),
     }.to_account_metas(None);
     Instruction {
         data,
@@ -118,3 +706,238 @@ pub fn resolve_account_close(
         program_id: account_history_program::id(),
     }
 }
+
+/// Take rewards out of the reward vault of a history account. Lamports are sent to the
+/// close authority, and tokens to `recipient_tokens`, which is required for token reward vaults.
+pub fn withdraw_rewards(
+    close_authority: Pubkey,
+    account_history: Pubkey,
+    amount: u64,
+    recipient_tokens: Option<Pubkey>,
+) -> Instruction {
+    let data = account_history_program::instruction::WithdrawRewards {
+        amount,
+    }.data();
+    let (vault_tokens, recipient_tokens, token_program) = reward_token_accounts(&account_history, recipient_tokens);
+    let accounts = account_history_program::accounts::WithdrawRewards {
+        close_authority,
+        account_state_history: account_history,
+        reward_vault: reward_vault_address(&account_history).0,
+        vault_tokens,
+        recipient_tokens,
+        token_program,
+    }.to_account_metas(None);
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+/// Update a history account, passing its reward vault to collect the bounty.
+/// The watched accounts must be in the same order as they are stored on the history account.
+/// Token bounties are paid to `recipient_tokens`, which is required for token reward vaults.
+pub fn update_with_reward(
+    signer: Pubkey,
+    account_history: Pubkey,
+    watched_accounts: &[Pubkey],
+    recipient_tokens: Option<Pubkey>,
+) -> Result<Instruction> {
+    let (watched_account, additional_accounts) = watched_accounts
+        .split_first()
+        .ok_or_else(|| anyhow!("No watched accounts to update"))?;
+    let data = account_history_program::instruction::Update.data();
+    let (vault_tokens, recipient_tokens, token_program) = reward_token_accounts(&account_history, recipient_tokens);
+    let mut accounts = account_history_program::accounts::Update {
+        signer,
+        account_state_history: account_history,
+        watched_account: *watched_account,
+        reward_vault: Some(reward_vault_address(&account_history).0),
+        vault_tokens,
+        recipient_tokens,
+        token_program,
+    }.to_account_metas(None);
+    // The signer receives lamport bounties
+    accounts[0].is_writable = true;
+    accounts.extend(
+        additional_accounts
+            .iter()
+            .map(|pubkey| AccountMeta::new_readonly(*pubkey, false))
+    );
+    Ok(Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    })
+}
+
+pub fn resize_account_history(
+    close_authority: Pubkey,
+    account_history: Pubkey,
+    capacity: u32,
+) -> Instruction {
+    let data = account_history_program::instruction::ResizeAccountHistory {
+        capacity,
+    }.data();
+    let accounts = account_history_program::accounts::ResizeAccountHistory {
+        close_authority,
+        account_state_history: account_history,
+        system_program: System::id(),
+    }.to_account_metas(None);
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+pub fn set_close_authority(
+    close_authority: Pubkey,
+    account_history: Pubkey,
+    new_close_authority: Pubkey,
+    two_step: bool,
+) -> Instruction {
+    let data = account_history_program::instruction::SetCloseAuthority {
+        new_close_authority,
+        two_step,
+    }.data();
+    let accounts = account_history_program::accounts::SetCloseAuthority {
+        close_authority,
+        account_state_history: account_history,
+    }.to_account_metas(None);
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+pub fn accept_close_authority(
+    pending_close_authority: Pubkey,
+    account_history: Pubkey,
+) -> Instruction {
+    let data = account_history_program::instruction::AcceptCloseAuthority.data();
+    let accounts = account_history_program::accounts::AcceptCloseAuthority {
+        pending_close_authority,
+        account_state_history: account_history,
+    }.to_account_metas(None);
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+pub fn renounce_close_authority(
+    close_authority: Pubkey,
+    account_history: Pubkey,
+) -> Instruction {
+    let data = account_history_program::instruction::RenounceCloseAuthority.data();
+    let accounts = account_history_program::accounts::RenounceCloseAuthority {
+        close_authority,
+        account_state_history: account_history,
+    }.to_account_metas(None);
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+pub fn set_update_authority(
+    close_authority: Pubkey,
+    account_history: Pubkey,
+    new_update_authority: Option<Pubkey>,
+) -> Instruction {
+    let data = account_history_program::instruction::SetUpdateAuthority {
+        new_update_authority,
+    }.data();
+    let accounts = account_history_program::accounts::SetUpdateAuthority {
+        close_authority,
+        account_state_history: account_history,
+    }.to_account_metas(None);
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+pub fn query(
+    account_history: Pubkey,
+    query: HistoryQuery,
+) -> Instruction {
+    let data = account_history_program::instruction::Query {
+        query,
+    }.data();
+    let accounts = account_history_program::accounts::QueryAccountHistory {
+        account_state_history: account_history,
+    }.to_account_metas(None);
+    Instruction {
+        data,
+        accounts,
+        program_id: account_history_program::id(),
+    }
+}
+
+/// Answer a query about a history account by simulating the `query` instruction.
+/// The fee payer does not sign, and is only needed to build the transaction.
+pub fn simulate_query(
+    client: &RpcClient,
+    fee_payer: Pubkey,
+    account_history: Pubkey,
+    query: HistoryQuery,
+) -> Result<QueryResult> {
+    simulate_return_data(client, &[self::query(account_history, query)], fee_payer)
+}
+
+/// Simulate instructions, and deserialize the return data of the last one.
+fn simulate_return_data<T: AnchorDeserialize>(client: &RpcClient, ixs: &[Instruction], fee_payer: Pubkey) -> Result<T> {
+    let tx = Transaction::new_with_payer(ixs, Some(&fee_payer));
+    let result = client.simulate_transaction_with_config(&tx, RpcSimulateTransactionConfig {
+        sig_verify: false,
+        replace_recent_blockhash: true,
+        ..Default::default()
+    })?.value;
+    if let Some(err) = result.err {
+        return Err(anyhow!("Simulation failed: {:?} {:#?}", err, result.logs));
+    }
+    // Trailing zeros are trimmed from simulated return data, leaving none at all
+    // if every byte is zero, so restore them before deserializing.
+    let mut data = match result.return_data {
+        Some(return_data) => base64::decode(return_data.data.0)?,
+        None => vec![],
+    };
+    data.resize(solana_sdk::program::MAX_RETURN_DATA, 0);
+    Ok(T::deserialize(&mut data.as_slice())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::hash::hash;
+
+    #[test]
+    fn initialize_wire_format() {
+        let (payer, seed, watched_account) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        // Without options, the instruction is encoded as it was before options existed
+        let ix = initialize_account_history(10, vec![(64, 8)], Some(2), None, None, payer, seed, watched_account);
+        let expected = [
+            &hash(b"global:initialize_account_history").to_bytes()[..8],
+            &10u32.to_le_bytes(),
+            &1u32.to_le_bytes(),
+            &64u32.to_le_bytes(),
+            &8u32.to_le_bytes(),
+            &[1],
+            &2u32.to_le_bytes(),
+            &[0],
+        ].concat();
+        assert_eq!(ix.data, expected);
+        assert_eq!(ix.accounts.len(), 5);
+
+        let options = HistoryOptions { pin_owners: true, ..Default::default() };
+        let ix = initialize_account_history(10, vec![(64, 8)], Some(2), None, Some(options), payer, seed, watched_account);
+        assert_eq!(ix.data[..8], hash(b"global:initialize_account_history_with_options").to_bytes()[..8]);
+        assert_eq!(ix.accounts.len(), 5);
+    }
+}
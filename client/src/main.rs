@@ -1,15 +1,23 @@
-
+use std::path::Path;
+use std::time::Duration;
 use anyhow::anyhow;
 use clap::{IntoApp, Parser};
 use solana_clap_v3_utils::keypair::pubkey_from_path;
 use solana_client::rpc_client::RpcClient;
+use solana_sdk::instruction::Instruction;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Keypair;
 use solana_sdk::signer::Signer;
 use solana_sdk::transaction::Transaction;
-use account_history_client::{DataRegion, initialize_account_close, initialize_account_history, resolve_account_close, update};
+use account_history_client::{accept_close_authority, cancel_account_close, DataRegion, find_labeled_account_history, find_labeled_account_history_address, initialize_account_close, initialize_account_history, initialize_labeled_account_history, initialize_multi_account_history, initialize_pyth_price_history, history_to_json, parse_field_schema, parse_preset, parse_shrink_policy, PresetKind, token_preset_regions, parse_unchanged_policy, renounce_close_authority, resize_account_history, resolve_account_close, resolve_account_close_with_reward_vault, initialize_reward_vault, initialize_token_reward_vault, deposit_rewards, withdraw_rewards, update_with_reward, set_close_authority, set_update_authority, update_multi_account};
+use account_history_client::archive::Archive;
 use account_history_client::config::{KeypairArg, UrlArg};
+use account_history_client::crank::{read_history_list, Crank, CrankConfig};
+use account_history_client::export::{export_history, parse_export_format, read_account_dump, ExportFormat};
+use account_history_client::inspect::{interpret_regions, parse_output_format, parse_region_format, region_formats, HistoryView, OutputFormat, RegionFormat};
 use account_history_program::state::{account_history_address, AccountHistoryRaw};
+use account_history_program::state::options::{HistoryOptions, ShrinkPolicy, UnchangedPolicy};
+use account_history_program::state::schema::FieldSchema;
 /// Solana Account History CLI
 ///
 /// Interact with the Account History Program,
@@ -36,7 +44,15 @@ impl Opt {
                 capacity,
                 min_slot_delay,
                 min_close_delay,
+                shrink_policy,
+                pin_owners,
+                unchanged_policy,
+                label,
+                preset,
+                max_price_age,
+                schema,
                 watched_account,
+                additional_accounts,
                 data_regions,
             } => {
                 let watched_account = pubkey_from_path(
@@ -46,95 +62,384 @@ impl Opt {
                     &mut None,
                 ).map_err(|_| anyhow!("Invalid pubkey or path: {}", watched_account))?;
                 let seed = Keypair::new();
-                let ix = initialize_account_history(
-                    capacity,
-                    data_regions.into_iter().map(|d| d.into()).collect(),
-                    min_slot_delay,
-                    min_close_delay,
+                let options = Some(HistoryOptions {
+                    shrink_policy: shrink_policy.unwrap_or_default(),
+                    // Presets record accounts of a known owner, so that owner is pinned
+                    pin_owners: pin_owners || preset.is_some(),
+                    unchanged_policy: unchanged_policy.unwrap_or_default(),
+                    // Presets describe their own elements
+                    schema: match preset {
+                        Some(preset) if schema.is_empty() => preset.schema(),
+                        _ => schema,
+                    },
+                });
+                let seed_address = account_history_address(seed.pubkey().to_bytes()).0;
+                if preset.is_some() && (label.is_some() || !additional_accounts.is_empty() || !data_regions.is_empty()) {
+                    return Err(anyhow!("Presets configure their own data regions, and cannot be labeled"));
+                }
+                if preset.is_none() && data_regions.is_empty() {
+                    return Err(anyhow!("Either data regions or a preset must be given"));
+                }
+                let (ix, addr) = if let Some(PresetKind::Pyth) = preset {
+                    let max_price_age = max_price_age
+                        .ok_or(anyhow!("The pyth preset requires --max-price-age"))?;
+                    let ix = initialize_pyth_price_history(
+                        capacity,
+                        max_price_age,
+                        min_slot_delay,
+                        min_close_delay,
+                        None,
+                        options,
+                        signer_pubkey,
+                        seed.pubkey(),
+                        watched_account,
+                    );
+                    (ix, seed_address)
+                } else if let Some(preset) = preset {
+                    let ix = initialize_account_history(
+                        capacity,
+                        token_preset_regions(&client, preset, &watched_account)?,
+                        min_slot_delay,
+                        min_close_delay,
+                        options,
+                        signer_pubkey,
+                        seed.pubkey(),
+                        watched_account,
+                    );
+                    (ix, seed_address)
+                } else if label.is_none()
+                    && additional_accounts.is_empty()
+                    && data_regions.iter().all(|r| r.account_index() == 0) {
+                    let ix = initialize_account_history(
+                        capacity,
+                        data_regions.into_iter().map(|d| d.into()).collect(),
+                        min_slot_delay,
+                        min_close_delay,
+                        options,
+                        signer_pubkey,
+                        seed.pubkey(),
+                        watched_account,
+                    );
+                    (ix, seed_address)
+                } else {
+                    let mut watched_accounts = vec![(watched_account, vec![])];
+                    for account in additional_accounts {
+                        let pubkey = pubkey_from_path(
+                            &matches,
+                            &account,
+                            "keypair",
+                            &mut None,
+                        ).map_err(|_| anyhow!("Invalid pubkey or path: {}", account))?;
+                        watched_accounts.push((pubkey, vec![]));
+                    }
+                    for region in data_regions {
+                        let (_, regions) = watched_accounts
+                            .get_mut(region.account_index() as usize)
+                            .ok_or(anyhow!("Invalid account index: {}", region.account_index()))?;
+                        regions.push(region.into());
+                    }
+                    match &label {
+                        Some(label) => {
+                            let addr = find_labeled_account_history_address(&watched_account, &signer_pubkey, label)?;
+                            let ix = initialize_labeled_account_history(
+                                capacity,
+                                watched_accounts,
+                                label,
+                                min_slot_delay,
+                                min_close_delay,
+                                None,
+                                options,
+                                signer_pubkey,
+                            )?;
+                            (ix, addr)
+                        },
+                        None => {
+                            let ix = initialize_multi_account_history(
+                                capacity,
+                                watched_accounts,
+                                min_slot_delay,
+                                min_close_delay,
+                                None,
+                                options,
+                                signer_pubkey,
+                                seed.pubkey(),
+                            );
+                            (ix, seed_address)
+                        },
+                    }
+                };
+                println!("Creating history account: {} watching data at {}", addr, watched_account);
+                // Labeled history accounts are not derived from a seed signer
+                let mut signers = vec![signer];
+                if label.is_none() {
+                    signers.push(Box::new(seed));
+                }
+                send_transaction(&client, &[ix], signers)?;
+            },
+            Subcommand::Find { creator, watched_account, label } => {
+                let creator = creator.unwrap_or(signer_pubkey);
+                match find_labeled_account_history(&client, &watched_account, &creator, &label)? {
+                    Some((address, mut data)) => {
+                        let history = AccountHistoryRaw::from_buffer(&mut data)?;
+                        println!("{}", address);
+                        println!("Capacity: {}", history.capacity());
+                        println!("Elements: {}", history.len());
+                        println!("Updates: {}", history.num_updates());
+                    },
+                    None => {
+                        return Err(anyhow!("No history account labeled {:?} by {} watching {}", label, creator, watched_account));
+                    },
+                }
+            },
+            Subcommand::Update { reward_tokens, history_account } => {
+                let mut account_data = client.get_account_data(&history_account)?;
+                let history = AccountHistoryRaw::from_buffer(&mut account_data)?;
+                // Collect the bounty of the reward vault, if any
+                let ix = if history.has_reward_vault() {
+                    update_with_reward(
+                        signer_pubkey,
+                        history_account,
+                        history.watched_accounts(),
+                        reward_tokens,
+                    )?
+                } else {
+                    update_multi_account(
+                        signer_pubkey,
+                        history_account,
+                        history.watched_accounts(),
+                    )?
+                };
+                send_transaction(&client, &[ix], vec![signer])?;
+            },
+            Subcommand::Crank { file, poll_interval_ms, max_retries, reward_tokens, mut history_accounts } => {
+                if let Some(file) = file {
+                    history_accounts.extend(read_history_list(&file)?);
+                }
+                if history_accounts.is_empty() {
+                    return Err(anyhow!("No history accounts to crank"));
+                }
+                println!("Cranking {} history accounts", history_accounts.len());
+                let config = CrankConfig {
+                    histories: history_accounts,
+                    poll_interval: Duration::from_millis(poll_interval_ms),
+                    max_retries,
+                    reward_tokens,
+                };
+                Crank::new(&client, signer.as_ref(), config).run()?;
+            },
+            Subcommand::Inspect { output, interpret, limit, history_account } => {
+                let mut data = client.get_account_data(&history_account)?;
+                let history = AccountHistoryRaw::from_buffer(&mut data)?;
+                let view = HistoryView::new(&history, &interpret, limit)?;
+                print!("{}", view.render(output));
+            },
+            Subcommand::Export { format, output, append, interpret, dump, history_account } => {
+                let mut data = match (history_account, dump) {
+                    (Some(history_account), None) => client.get_account_data(&history_account)?,
+                    (None, Some(dump)) => read_account_dump(&dump)?,
+                    _ => return Err(anyhow!("Either a history account or --dump must be given")),
+                };
+                let history = AccountHistoryRaw::from_buffer(&mut data)?;
+                let export = export_history(&history, &interpret, format, Path::new(&output), append)?;
+                if export.missed > 0 {
+                    println!("Warning: {} elements were overwritten before they could be exported", export.missed);
+                }
+                println!("Exported {} elements to {}", export.rows.len(), output);
+            },
+            Subcommand::Archive { dir, file, once, poll_interval_ms, mut history_accounts } => {
+                if let Some(file) = file {
+                    history_accounts.extend(read_history_list(&file)?);
+                }
+                if history_accounts.is_empty() {
+                    return Err(anyhow!("No history accounts to archive"));
+                }
+                let archive = Archive::open(&dir)?;
+                if once {
+                    archive.poll(&client, &history_accounts)?;
+                } else {
+                    println!("Archiving {} history accounts to {}", history_accounts.len(), dir);
+                    archive.watch(&client, &history_accounts, Duration::from_millis(poll_interval_ms))?;
+                }
+            },
+            Subcommand::QueryArchive { dir, start_slot, end_slot, interpret, history_account } => {
+                let archive = Archive::open(&dir)?;
+                // The account may have been closed since it was archived
+                let mut data = client.get_account_data(&history_account).unwrap_or_default();
+                let history = AccountHistoryRaw::from_buffer(&mut data).ok();
+                let merged = archive.merged(&history_account, history.as_ref())?;
+                for (first, last) in &merged.gaps {
+                    eprintln!("Missing updates {} to {}", first, last);
+                }
+                let formats = match &history {
+                    Some(history) => Some(region_formats(&interpret, history.data_regions().len())?),
+                    None => None,
+                };
+                for element in merged.range_between_slots(start_slot.unwrap_or(0), end_slot.unwrap_or(u64::MAX)) {
+                    let values = match (&history, &formats) {
+                        (Some(history), Some(formats)) if !element.is_tombstone() => {
+                            interpret_regions(&element.element, history.data_regions(), formats)?
+                        },
+                        _ => vec![RegionFormat::Hex.format(&element.element[8..])?],
+                    };
+                    println!("{}", serde_json::json!({
+                        "update": element.update,
+                        "slot": element.slot(),
+                        "tombstone": element.is_tombstone(),
+                        "values": values,
+                    }));
+                }
+            },
+            Subcommand::Decode { history_account } => {
+                let mut data = client.get_account_data(&history_account)?;
+                println!("{}", serde_json::to_string_pretty(&history_to_json(&mut data)?)?);
+            },
+            Subcommand::InitClose { history_account } => {
+                println!("Initializing account close procedure on {}", history_account);
+                let ix = initialize_account_close(
                     signer_pubkey,
-                    seed.pubkey(),
-                    watched_account,
+                    history_account,
                 );
-                let addr = account_history_address(seed.pubkey().to_bytes()).0;
-                println!("Creating history account: {} watching data at {}", addr, watched_account);
-                let tx = Transaction::new_signed_with_payer(
-                    &[ix],
-                    Some(&signer_pubkey),
-                    &vec![signer, Box::new(seed)],
-                    client.get_latest_blockhash()?
+                send_transaction(&client, &[ix], vec![signer])?;
+            },
+            Subcommand::CancelClose { history_account } => {
+                println!("Cancelling account close procedure on {}", history_account);
+                let ix = cancel_account_close(
+                    signer_pubkey,
+                    history_account,
                 );
-                let signature = client.send_transaction(&tx)
-                    .map_err(|e| {
-                        println!("{:#?}", &e);
-                        e
-                    })?;
-                println!("{}", signature);
-            },
-            Subcommand::Update { history_account } => {
+                send_transaction(&client, &[ix], vec![signer])?;
+            },
+            Subcommand::ResolveClose { history_account, rent_recipient, reward_tokens } => {
+                println!("Resolving account close procedure on {}", history_account);
                 let mut account_data = client.get_account_data(&history_account)?;
                 let history = AccountHistoryRaw::from_buffer(&mut account_data)?;
-                let ix = update(
+                let ix = if history.has_reward_vault() {
+                    resolve_account_close_with_reward_vault(
+                        signer_pubkey,
+                        history_account,
+                        rent_recipient,
+                        reward_tokens,
+                    )
+                } else {
+                    resolve_account_close(
+                        signer_pubkey,
+                        history_account,
+                        rent_recipient,
+                    )
+                };
+                send_transaction(&client, &[ix], vec![signer])?;
+            },
+            Subcommand::InitRewardVault { bounty, mint, history_account } => {
+                let ix = match mint {
+                    Some(mint) => {
+                        println!("Paying {} tokens of {} for each update of {}", bounty, mint, history_account);
+                        initialize_token_reward_vault(signer_pubkey, history_account, mint, bounty)
+                    },
+                    None => {
+                        println!("Paying {} lamports for each update of {}", bounty, history_account);
+                        initialize_reward_vault(signer_pubkey, history_account, bounty)
+                    },
+                };
+                send_transaction(&client, &[ix], vec![signer])?;
+            },
+            Subcommand::DepositRewards { amount, tokens, history_account } => {
+                println!("Depositing {} into the reward vault of {}", amount, history_account);
+                let ix = deposit_rewards(
                     signer_pubkey,
                     history_account,
-                    history.associated_account(),
+                    amount,
+                    tokens,
                 );
-                let tx = Transaction::new_signed_with_payer(
-                    &[ix],
-                    Some(&signer_pubkey),
-                    &vec![signer],
-                    client.get_latest_blockhash()?
+                send_transaction(&client, &[ix], vec![signer])?;
+            },
+            Subcommand::WithdrawRewards { amount, tokens, history_account } => {
+                println!("Withdrawing {} from the reward vault of {}", amount, history_account);
+                let ix = withdraw_rewards(
+                    signer_pubkey,
+                    history_account,
+                    amount,
+                    tokens,
                 );
-                let signature = client.send_transaction(&tx)
-                    .map_err(|e| {
-                        println!("{:#?}", &e);
-                        e
-                    })?;
-                println!("{}", signature);
+                send_transaction(&client, &[ix], vec![signer])?;
             },
-            Subcommand::InitClose { history_account } => {
-                println!("Initializing account close procedure on {}", history_account);
-                let ix = initialize_account_close(
+            Subcommand::Resize { capacity, history_account } => {
+                println!("Resizing {} to a capacity of {}", history_account, capacity);
+                let ix = resize_account_history(
                     signer_pubkey,
                     history_account,
+                    capacity,
                 );
-                let tx = Transaction::new_signed_with_payer(
-                    &[ix],
-                    Some(&signer_pubkey),
-                    &vec![signer],
-                    client.get_latest_blockhash()?
+                send_transaction(&client, &[ix], vec![signer])?;
+            },
+            Subcommand::SetCloseAuthority { two_step, new_close_authority, history_account } => {
+                if two_step {
+                    println!("Proposing {} as close authority of {}", new_close_authority, history_account);
+                } else {
+                    println!("Setting {} as close authority of {}", new_close_authority, history_account);
+                }
+                let ix = set_close_authority(
+                    signer_pubkey,
+                    history_account,
+                    new_close_authority,
+                    two_step,
                 );
-                let signature = client.send_transaction(&tx)
-                    .map_err(|e| {
-                        println!("{:#?}", &e);
-                        e
-                    })?;
-                println!("{}", signature);
-            },
-            Subcommand::ResolveClose { history_account, rent_recipient } => {
-                println!("Resolving account close procedure on {}", history_account);
-                let ix = resolve_account_close(
+                send_transaction(&client, &[ix], vec![signer])?;
+            },
+            Subcommand::AcceptCloseAuthority { history_account } => {
+                println!("Accepting close authority of {}", history_account);
+                let ix = accept_close_authority(
                     signer_pubkey,
                     history_account,
-                    rent_recipient,
                 );
-                let tx = Transaction::new_signed_with_payer(
-                    &[ix],
-                    Some(&signer_pubkey),
-                    &vec![signer],
-                    client.get_latest_blockhash()?
+                send_transaction(&client, &[ix], vec![signer])?;
+            },
+            Subcommand::RenounceCloseAuthority { history_account } => {
+                println!("Renouncing close authority of {}", history_account);
+                let ix = renounce_close_authority(
+                    signer_pubkey,
+                    history_account,
                 );
-                let signature = client.send_transaction(&tx)
-                    .map_err(|e| {
-                        println!("{:#?}", &e);
-                        e
-                    })?;
-                println!("{}", signature);
+                send_transaction(&client, &[ix], vec![signer])?;
+            },
+            Subcommand::SetUpdateAuthority { new_update_authority, permissionless: _, history_account } => {
+                match new_update_authority {
+                    Some(authority) => println!("Setting {} as update authority of {}", authority, history_account),
+                    None => println!("Making updates to {} permissionless", history_account),
+                }
+                let ix = set_update_authority(
+                    signer_pubkey,
+                    history_account,
+                    new_update_authority,
+                );
+                send_transaction(&client, &[ix], vec![signer])?;
             },
         }
         Ok(())
     }
 }
 
+/// Sign and send a transaction paid for by the first signer,
+/// printing the resulting signature.
+fn send_transaction(
+    client: &RpcClient,
+    instructions: &[Instruction],
+    signers: Vec<Box<dyn Signer>>,
+) -> anyhow::Result<()> {
+    let tx = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&signers[0].pubkey()),
+        &signers,
+        client.get_latest_blockhash()?
+    );
+    let signature = client.send_transaction(&tx)
+        .map_err(|e| {
+            println!("{:#?}", &e);
+            e
+        })?;
+    println!("{}", signature);
+    Ok(())
+}
+
 #[derive(Parser, Debug)]
 pub enum Subcommand {
     /// Create a new history account.
@@ -150,14 +455,165 @@ pub enum Subcommand {
         /// Defaults to 0.
         #[clap(long)]
         min_close_delay: Option<u32>,
+        /// What to do on update when a watched account is too small to hold its data regions,
+        /// for example because it was closed: error, tombstone or close.
+        /// Defaults to error.
+        #[clap(long, parse(try_from_str = parse_shrink_policy))]
+        shrink_policy: Option<ShrinkPolicy>,
+        /// Record the owner program of each watched account,
+        /// and refuse updates once an owner has changed.
+        #[clap(long)]
+        pin_owners: bool,
+        /// What to do on update when the data regions are unchanged
+        /// since the most recent element: record, skip or reject.
+        /// Defaults to record.
+        #[clap(long, parse(try_from_str = parse_unchanged_policy))]
+        unchanged_policy: Option<UnchangedPolicy>,
+        /// Create the history account at an address derived from the watched account,
+        /// the signer and this label, so that it can later be found with `find`.
+        #[clap(long)]
+        label: Option<String>,
+        /// Record a well-known account type, whose data regions are configured
+        /// automatically: pyth, token-balance, token-delegated-amount, token-delegate, token-state
+        /// or mint-supply.
+        /// The owner of the watched account is pinned, and Pyth prices are validated on every update.
+        #[clap(long, parse(try_from_str = parse_preset))]
+        preset: Option<PresetKind>,
+        /// With the pyth preset, the maximum number of slots since the price was published
+        /// for an update to be accepted.
+        #[clap(long)]
+        max_price_age: Option<u32>,
+        /// Describe a field of each element as name:offset:type, e.g. balance:8:u64,
+        /// so that the history account can be decoded with `decode`. The offset counts the
+        /// 8 bytes of the slot. Presets describe their own elements.
+        #[clap(long = "field", parse(try_from_str = parse_field_schema))]
+        schema: Vec<FieldSchema>,
         /// The target account whose data to watch
         watched_account: String,
+        /// Additional accounts to watch, whose data is recorded
+        /// together with the target account's data.
+        #[clap(long = "watch")]
+        additional_accounts: Vec<String>,
         /// Account data regions to capture and store on each element of the history account.
-        #[clap(min_values=1, parse(try_from_str = DataRegion::try_from))]
+        /// Regions on additional accounts are prefixed with the account's index, e.g. 1:64:8.
+        /// Each element stores the regions of each account in turn.
+        /// Not used with a preset.
+        #[clap(parse(try_from_str = DataRegion::try_from))]
         data_regions: Vec<DataRegion>,
     },
+    /// Find a labeled history account by the account it watches,
+    /// its creator and its label.
+    Find {
+        /// The account that created the history account. Defaults to the signer.
+        #[clap(long, parse(try_from_str=Pubkey::try_from))]
+        creator: Option<Pubkey>,
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        watched_account: Pubkey,
+        label: String,
+    },
+    /// Print the elements of a history account as JSON, from oldest to newest,
+    /// using the schema stored on the account.
+    Decode {
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_account: Pubkey,
+    },
     /// Store a current snapshot of account data.
     Update {
+        /// Token account receiving the bounty of a token reward vault
+        #[clap(long, parse(try_from_str=Pubkey::try_from))]
+        reward_tokens: Option<Pubkey>,
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_account: Pubkey,
+    },
+    /// Keep history accounts updated, sending an update as soon as
+    /// each history's minimum slot delay has elapsed.
+    Crank {
+        /// File listing history accounts, one per line
+        #[clap(long)]
+        file: Option<String>,
+        /// Delay between two polls of the history accounts
+        #[clap(long, default_value = "400")]
+        poll_interval_ms: u64,
+        /// How many times to re-send an update whose blockhash expired
+        #[clap(long, default_value = "3")]
+        max_retries: usize,
+        /// Token account receiving the bounties of token reward vaults
+        #[clap(long, parse(try_from_str=Pubkey::try_from))]
+        reward_tokens: Option<Pubkey>,
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_accounts: Vec<Pubkey>,
+    },
+    /// Print the header and elements of a history account, newest first.
+    Inspect {
+        /// One of table, json or csv. CSV only lists the elements.
+        #[clap(long, default_value = "table", parse(try_from_str = parse_output_format))]
+        output: OutputFormat,
+        /// How to read each data region: u64, i64, u128, pubkey or hex.
+        /// Either one per region, or one for all regions. Defaults to hex.
+        #[clap(long, use_value_delimiter = true, parse(try_from_str = parse_region_format))]
+        interpret: Vec<RegionFormat>,
+        /// Only list the most recent elements
+        #[clap(long)]
+        limit: Option<usize>,
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_account: Pubkey,
+    },
+    /// Write the elements of a history account to a CSV or JSON Lines file, from oldest to newest.
+    Export {
+        /// One of csv or jsonl
+        #[clap(long, default_value = "csv", parse(try_from_str = parse_export_format))]
+        format: ExportFormat,
+        /// The file to write
+        #[clap(long)]
+        output: String,
+        /// Only add the elements recorded since the last row of an existing file
+        #[clap(long)]
+        append: bool,
+        /// How to read each data region: u64, i64, u128, pubkey or hex.
+        /// Either one per region, or one for all regions.
+        /// Defaults to the account's schema, if any, and otherwise hex.
+        #[clap(long, use_value_delimiter = true, parse(try_from_str = parse_region_format))]
+        interpret: Vec<RegionFormat>,
+        /// Read the account from a file saved with `solana account`, instead of the cluster
+        #[clap(long)]
+        dump: Option<String>,
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_account: Option<Pubkey>,
+    },
+    /// Keep an off-chain archive of the elements of history accounts,
+    /// so that elements evicted from the ring buffer are not lost.
+    Archive {
+        /// Directory holding the archive
+        #[clap(long)]
+        dir: String,
+        /// File listing history accounts, one per line
+        #[clap(long)]
+        file: Option<String>,
+        /// Archive the accounts once and exit
+        #[clap(long)]
+        once: bool,
+        /// Maximum delay between two polls of the history accounts.
+        /// Accounts are polled sooner when elements could otherwise be evicted unseen.
+        #[clap(long, default_value = "60000")]
+        poll_interval_ms: u64,
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_accounts: Vec<Pubkey>,
+    },
+    /// Print the archived and on-chain elements of a history account as JSON lines,
+    /// from oldest to newest. Missing updates are reported on stderr.
+    QueryArchive {
+        /// Directory holding the archive
+        #[clap(long)]
+        dir: String,
+        #[clap(long)]
+        start_slot: Option<u64>,
+        #[clap(long)]
+        end_slot: Option<u64>,
+        /// How to read each data region: u64, i64, u128, pubkey or hex.
+        /// Either one per region, or one for all regions. Defaults to hex.
+        /// Elements are printed as hex when the history account no longer exists.
+        #[clap(long, use_value_delimiter = true, parse(try_from_str = parse_region_format))]
+        interpret: Vec<RegionFormat>,
         #[clap(parse(try_from_str=Pubkey::try_from))]
         history_account: Pubkey,
     },
@@ -166,10 +622,96 @@ pub enum Subcommand {
         #[clap(parse(try_from_str=Pubkey::try_from))]
         history_account: Pubkey,
     },
+    /// Cancel the process to close a history account, allowing updates to resume.
+    CancelClose {
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_account: Pubkey,
+    },
     /// Finish the process to close a history account.
     ResolveClose {
         #[clap(long, parse(try_from_str=Pubkey::try_from))]
         rent_recipient: Option<Pubkey>,
+        /// Token account receiving the remaining tokens of a token reward vault
+        #[clap(long, parse(try_from_str=Pubkey::try_from))]
+        reward_tokens: Option<Pubkey>,
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_account: Pubkey,
+    },
+    /// Attach a reward vault paying a bounty for each update of a history account.
+    InitRewardVault {
+        /// Amount paid for each update
+        #[clap(long)]
+        bounty: u64,
+        /// Pay the bounty in tokens of this mint instead of lamports
+        #[clap(long, parse(try_from_str=Pubkey::try_from))]
+        mint: Option<Pubkey>,
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_account: Pubkey,
+    },
+    /// Fund the reward vault of a history account.
+    DepositRewards {
+        #[clap(long)]
+        amount: u64,
+        /// Token account to take the tokens of a token reward vault from
+        #[clap(long, parse(try_from_str=Pubkey::try_from))]
+        tokens: Option<Pubkey>,
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_account: Pubkey,
+    },
+    /// Take rewards out of the reward vault of a history account.
+    WithdrawRewards {
+        #[clap(long)]
+        amount: u64,
+        /// Token account receiving the tokens of a token reward vault
+        #[clap(long, parse(try_from_str=Pubkey::try_from))]
+        tokens: Option<Pubkey>,
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_account: Pubkey,
+    },
+    /// Grow or shrink the capacity of a history account,
+    /// keeping as many of the most recent elements as will fit.
+    Resize {
+        /// The new number of data elements to store
+        #[clap(long)]
+        capacity: u32,
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_account: Pubkey,
+    },
+    /// Transfer the close authority of a history account.
+    SetCloseAuthority {
+        /// Only propose the new close authority,
+        /// who must then sign to accept the transfer.
+        #[clap(long)]
+        two_step: bool,
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        new_close_authority: Pubkey,
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_account: Pubkey,
+    },
+    /// Accept a proposed transfer of the close authority of a history account.
+    AcceptCloseAuthority {
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_account: Pubkey,
+    },
+    /// Irrevocably give up the close authority of a history account,
+    /// so that it can never be closed, resized or reconfigured.
+    RenounceCloseAuthority {
+        #[clap(parse(try_from_str=Pubkey::try_from))]
+        history_account: Pubkey,
+    },
+    /// Change the update authority of a history account.
+    SetUpdateAuthority {
+        /// The new update authority
+        #[clap(
+            long,
+            required_unless_present = "permissionless",
+            conflicts_with = "permissionless",
+            parse(try_from_str=Pubkey::try_from),
+        )]
+        new_update_authority: Option<Pubkey>,
+        /// Allow anyone to update the history account, instead of a single update authority
+        #[clap(long)]
+        permissionless: bool,
         #[clap(parse(try_from_str=Pubkey::try_from))]
         history_account: Pubkey,
     },
@@ -180,4 +722,4 @@ fn main() -> anyhow::Result<()> {
     let opt = Opt::parse();
     opt.process()?;
     Ok(())
-}
\ No newline at end of file
+}
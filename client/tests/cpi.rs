@@ -0,0 +1,105 @@
+use std::thread::sleep;
+use std::time::Duration;
+use anchor_lang::prelude::*;
+use anchor_lang::InstructionData;
+use clap::{IntoApp, Parser};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::clock::Slot;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::Transaction;
+use account_history_client::config::KeypairArg;
+use account_history_client::initialize_multi_account_history;
+use account_history_program::state::account_history_address;
+use account_history_program::state::interpreted::{AccountHistory, AccountHistoryIterator};
+use bytemuck::{Zeroable, Pod};
+use cpi_example::{COUNT_OFFSET, history_authority_address};
+
+#[derive(Default, Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+struct HistoricalCount {
+    slot: Slot,
+    count: u64,
+}
+
+fn send(client: &RpcClient, ixs: &[Instruction], signers: Vec<Box<dyn Signer>>) {
+    let tx = Transaction::new_signed_with_payer(
+        ixs,
+        Some(&signers[0].pubkey()),
+        &signers,
+        client.get_latest_blockhash().unwrap()
+    );
+    let signature = client.send_transaction(&tx)
+        .map_err(|e| {
+            println!("{:#?}", &e);
+            e
+        }).unwrap();
+    println!("{}", signature);
+    sleep(Duration::from_secs(1));
+}
+
+/// Record a counter's history through CPIs from the example program,
+/// signed by the example program's PDA.
+#[test]
+fn cpi() {
+    let matches = KeypairArg::into_app().get_matches();
+    let keypair = || KeypairArg::parse().resolve(&matches, None).unwrap();
+    let authority = keypair().pubkey();
+    println!("Signer: {}", authority);
+    let client = RpcClient::new_with_commitment("http://localhost:8899", CommitmentConfig::processed());
+
+    println!("Initializing counter");
+    let counter = Keypair::new();
+    let counter_pubkey = counter.pubkey();
+    let ix = Instruction {
+        data: cpi_example::instruction::InitializeCounter.data(),
+        accounts: cpi_example::accounts::InitializeCounter {
+            authority,
+            counter: counter_pubkey,
+            system_program: System::id(),
+        }.to_account_metas(None),
+        program_id: cpi_example::id(),
+    };
+    send(&client, &[ix], vec![keypair(), Box::new(counter)]);
+
+    println!("Initializing account history");
+    // The example program's PDA is the only account allowed to push updates
+    let history_authority = history_authority_address().0;
+    let seed = Keypair::new();
+    let history_address = account_history_address(seed.pubkey().to_bytes()).0;
+    let ix = initialize_multi_account_history(
+        5,
+        vec![(counter_pubkey, vec![(COUNT_OFFSET, 8)])],
+        None,
+        None,
+        Some(history_authority),
+        None,
+        authority,
+        seed.pubkey(),
+    );
+    send(&client, &[ix], vec![keypair(), Box::new(seed)]);
+
+    for _ in 0..3 {
+        let ix = Instruction {
+            data: cpi_example::instruction::Increment.data(),
+            accounts: cpi_example::accounts::Increment {
+                authority,
+                counter: counter_pubkey,
+                history_authority,
+                account_state_history: history_address,
+                account_history_program: account_history_program::id(),
+            }.to_account_metas(None),
+            program_id: cpi_example::id(),
+        };
+        send(&client, &[ix], vec![keypair()]);
+    }
+
+    let mut data = client.get_account_data(&history_address).unwrap();
+    let count_history = AccountHistory::<HistoricalCount>::from_buffer(&mut data).unwrap();
+    println!("{:#?}", count_history);
+    assert_eq!(count_history.len(), 4);
+    let counts: Vec<u64> = AccountHistoryIterator::from(&count_history).map(|c| c.count).collect();
+    assert_eq!(counts, vec![3, 2, 1, 0]);
+}
@@ -0,0 +1,45 @@
+use clap::{IntoApp, Parser};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use account_history_client::config::KeypairArg;
+use account_history_client::initialize_pyth_price_history;
+use account_history_program::errors::AccountHistoryProgramError;
+
+/// A Pyth price history cannot be backed by an account that the Pyth program does not own
+#[test]
+fn fake_price_account() {
+    let matches = KeypairArg::into_app().get_matches();
+    let keypair = KeypairArg::parse().resolve(&matches, None).unwrap();
+    let client = RpcClient::new_with_commitment("http://localhost:8899", CommitmentConfig::processed());
+    let seed = Keypair::new();
+    // Any account can be made to look like a price account, so pass a system account
+    let ix = initialize_pyth_price_history(
+        10,
+        25,
+        None,
+        None,
+        None,
+        None,
+        keypair.pubkey(),
+        seed.pubkey(),
+        keypair.pubkey(),
+    );
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&keypair.pubkey()),
+        &vec![keypair, Box::new(seed)],
+        client.get_latest_blockhash().unwrap()
+    );
+    let error = client.send_transaction(&tx).unwrap_err();
+    assert_eq!(
+        error.get_transaction_error(),
+        Some(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(6000 + AccountHistoryProgramError::InvalidPriceAccount as u32),
+        )),
+    );
+}
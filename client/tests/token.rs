@@ -4,7 +4,6 @@ use anchor_lang::prelude::*;
 use anchor_spl::token::Token;
 use clap::{IntoApp, Parser};
 use solana_client::rpc_client::RpcClient;
-use solana_sdk::clock::Slot;
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::signature::Keypair;
 use solana_sdk::signer::Signer;
@@ -13,21 +12,15 @@ use spl_associated_token_account::get_associated_token_address;
 use spl_associated_token_account::instruction::create_associated_token_account;
 use spl_token::instruction::{initialize_mint, mint_to};
 use account_history_client::config::KeypairArg;
-use account_history_client::{initialize_account_history, update};
+use account_history_client::{initialize_account_history, token_preset_regions, update, PresetKind};
+use account_history_program::presets::token::{HistoricalBalance, TOKEN_BALANCE_REGION};
 use account_history_program::state::account_history_address;
+use account_history_program::state::options::HistoryOptions;
 use account_history_program::state::interpreted::{AccountHistory, AccountHistoryIterator, AccountHistoryIteratorRev};
-use bytemuck::{Zeroable, Pod};
 use solana_sdk::program_pack::Pack;
 use solana_sdk::system_instruction::create_account;
 use spl_token::state::Mint;
 
-#[derive(Default, Debug, Clone, Copy, Zeroable, Pod)]
-#[repr(C)]
-struct HistoricalBalance {
-    slot: Slot,
-    balance: u64,
-}
-
 /// Record token data
 #[test]
 fn token() {
@@ -78,12 +71,19 @@ fn token() {
     let watched_account = get_associated_token_address(&keypair.pubkey(), &mint_pubkey);
     let seed = Keypair::new();
     let history_address = account_history_address(seed.pubkey().to_bytes()).0;
-    // Balance is stored at region 64:8
+    // The token balance preset resolves to the region of the balance
+    let regions = token_preset_regions(&client, PresetKind::TokenBalance, &watched_account).unwrap();
+    assert_eq!(regions, vec![TOKEN_BALANCE_REGION]);
+    let options = HistoryOptions {
+        schema: PresetKind::TokenBalance.schema(),
+        ..Default::default()
+    };
     let ix = initialize_account_history(
         3,
-        vec![(64, 8)],
+        regions,
         None,
         None,
+        Some(options),
         keypair.pubkey(),
         seed.pubkey(),
         watched_account,
@@ -148,7 +148,7 @@ fn token() {
     let mut data = client.get_account_data(
         &history_address,
     ).unwrap();
-    let balance_history = AccountHistory::<HistoricalBalance>::from_buffer(&mut data).unwrap();
+    let balance_history = AccountHistory::<HistoricalBalance>::from_buffer_checked(&mut data).unwrap();
     println!("{:#?}", balance_history);
     assert_eq!(balance_history.most_recent_entry().balance, 7000);
     let iterator = AccountHistoryIterator::from(&balance_history);
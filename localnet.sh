@@ -1,3 +1,3 @@
 #!/bin/sh
 
-anchor build && solana-test-validator --bpf-program Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS target/deploy/account_history_program.so --reset
+anchor build && solana-test-validator --bpf-program Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS target/deploy/account_history_program.so --bpf-program Hprmqh5DdTaS7xnA42kKRY7Fm42VP6o426g7UeUZamks target/deploy/cpi_example.so --reset
@@ -0,0 +1,56 @@
+//! Typed helpers for calling this program from other Anchor programs.
+//! Requires the `cpi` feature.
+use anchor_lang::prelude::*;
+use crate::cpi::accounts::Update;
+use crate::errors::AccountHistoryProgramError;
+
+/// Push a snapshot of `watched_accounts` onto a history account from another program.
+///
+/// The watched accounts must be in the same order as they are stored on the history account,
+/// and there must be at least one, or `NotCorrectAccount` is returned.
+/// When the update authority is a PDA of the calling program, pass its seeds as
+/// `signer_seeds` so that it signs via `invoke_signed`. Otherwise pass an empty slice.
+///
+/// The watched accounts are read as they are at the time of the call, so a program
+/// that has just mutated one of its own Anchor accounts should call `exit` on it first,
+/// to write its data back before the snapshot is taken.
+pub fn update<'info>(
+    account_history_program: AccountInfo<'info>,
+    update_authority: AccountInfo<'info>,
+    account_state_history: AccountInfo<'info>,
+    watched_accounts: &[AccountInfo<'info>],
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let (watched_account, additional_accounts) = match watched_accounts.split_first() {
+        Some(split) => split,
+        None => return err!(AccountHistoryProgramError::NotCorrectAccount),
+    };
+    let accounts = Update {
+        signer: update_authority,
+        account_state_history,
+        watched_account: watched_account.clone(),
+        reward_vault: None,
+        vault_tokens: None,
+        recipient_tokens: None,
+        token_program: None,
+    };
+    let ctx = CpiContext::new_with_signer(account_history_program, accounts, signer_seeds)
+        .with_remaining_accounts(additional_accounts.to_vec());
+    crate::cpi::update(ctx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_watched_accounts() {
+        let (key, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut lamports, mut data) = (0, vec![]);
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        assert_eq!(
+            update(account.clone(), account.clone(), account, &[], &[]).unwrap_err(),
+            AccountHistoryProgramError::NotCorrectAccount.into(),
+        );
+    }
+}
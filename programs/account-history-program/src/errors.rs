@@ -24,4 +24,54 @@ pub enum AccountHistoryProgramError {
     InvalidDataType,
     #[msg("Cannot deserialize account, invalid account tag")]
     InvalidAccountTag,
+    #[msg("Cannot resize account by more than 10KiB in a single instruction")]
+    ResizeTooLarge,
+    #[msg("Close authority cannot be the default pubkey, renounce it instead")]
+    InvalidCloseAuthority,
+    #[msg("Signer provided does not match the pending close authority")]
+    NotPendingCloseAuthority,
+    #[msg("Cannot watch more than the maximum number of accounts")]
+    TooManyWatchedAccounts,
+    #[msg("Cannot record more than the maximum number of data regions")]
+    TooManyDataRegions,
+    #[msg("Cannot deserialize account, unsupported layout version")]
+    UnsupportedVersion,
+    #[msg("Data regions must not overlap")]
+    OverlappingDataRegions,
+    #[msg("Data region extends past the end of the watched account's data")]
+    DataRegionOutOfBounds,
+    #[msg("Watched account is no longer owned by its pinned owner program")]
+    WatchedAccountOwnerChanged,
+    #[msg("Data regions are unchanged since the most recent update")]
+    DataUnchanged,
+    #[msg("Queried field is not numeric, or extends past the end of the element")]
+    InvalidQueryField,
+    #[msg("Watched account is not a valid Pyth price account")]
+    InvalidPriceAccount,
+    #[msg("Pyth price is not trading")]
+    PriceNotTrading,
+    #[msg("Pyth price is too old")]
+    StalePrice,
+    #[msg("Invalid schema")]
+    InvalidSchema,
+    #[msg("History account has no schema")]
+    MissingSchema,
+    #[msg("Type does not match the schema of the history account")]
+    SchemaMismatch,
+    #[msg("Remaining accounts must be (history, watched account) pairs")]
+    InvalidAccountPairs,
+    #[msg("History account is not writable or not owned by this program")]
+    InvalidHistoryAccount,
+    #[msg("Token reward vaults require the vault, recipient token account and token program")]
+    InvalidRewardTokenAccounts,
+    #[msg("The reward vault holds too few rewards")]
+    InsufficientRewards,
+    #[msg("The reward vault must be passed to be swept when closing")]
+    RewardVaultNotSwept,
+    #[msg("Query result does not fit in return data")]
+    QueryResultTooLarge,
+    #[msg("Reward vaults require a history account with a nonzero min_slot_delay")]
+    RewardVaultRequiresSlotDelay,
+    #[msg("Reward vault does not belong to the history account")]
+    InvalidRewardVault,
 }
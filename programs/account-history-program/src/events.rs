@@ -0,0 +1,135 @@
+use anchor_lang::prelude::*;
+
+/// A history account was created.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryInitialized {
+    pub account_history: Pubkey,
+    /// The accounts whose state is recorded, in the order their regions are stored.
+    pub watched_accounts: Vec<Pubkey>,
+    pub close_authority: Pubkey,
+    /// `None` when updates are permissionless.
+    pub update_authority: Option<Pubkey>,
+    pub capacity: u32,
+    /// Size of each element, including the slot.
+    pub data_element_size: u32,
+}
+
+/// A new element was recorded on a history account.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotPushed {
+    pub account_history: Pubkey,
+    /// Slot of the new element.
+    pub slot: u64,
+    /// Index of the new element in the ring buffer.
+    pub index: u32,
+    /// Total number of elements recorded since initialization.
+    pub num_updates: u64,
+    /// Whether the element is a tombstone, recorded while a watched account was too small.
+    pub tombstone: bool,
+}
+
+/// The close procedure of a history account was started, either by its close authority,
+/// or by an update under the `Close` shrink policy.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseInitiated {
+    pub account_history: Pubkey,
+    /// Slot at which the close was initiated.
+    pub slot: u64,
+    /// First slot at which the close can be resolved.
+    pub resolvable_slot: u64,
+}
+
+/// A pending close was cancelled, and the history account accepts updates again.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseCancelled {
+    pub account_history: Pubkey,
+    /// Slot at which the close was cancelled.
+    pub slot: u64,
+}
+
+/// A history account was closed, and its lamports sent to the rent recipient.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseResolved {
+    pub account_history: Pubkey,
+    pub rent_recipient: Pubkey,
+    pub lamports: u64,
+}
+
+/// A bounty was paid out of a reward vault for an update.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewardPaid {
+    pub account_history: Pubkey,
+    pub recipient: Pubkey,
+    /// Mint of the token bounty, or `None` for lamports.
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+}
+
+/// A reward vault was attached to a history account.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewardVaultInitialized {
+    pub account_history: Pubkey,
+    pub reward_vault: Pubkey,
+    /// Mint of the token bounty, or `None` for lamports.
+    pub mint: Option<Pubkey>,
+    pub bounty: u64,
+}
+
+/// Rewards were added to the reward vault of a history account.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewardsDeposited {
+    pub account_history: Pubkey,
+    pub depositor: Pubkey,
+    /// Mint of the token rewards, or `None` for lamports.
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+}
+
+/// Rewards were taken out of the reward vault of a history account by its close authority.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewardsWithdrawn {
+    pub account_history: Pubkey,
+    pub recipient: Pubkey,
+    /// Mint of the token rewards, or `None` for lamports.
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+}
+
+/// The capacity of a history account was changed.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryResized {
+    pub account_history: Pubkey,
+    pub capacity: u32,
+    /// Number of elements kept.
+    pub len: u32,
+}
+
+/// The close authority of a history account was transferred, proposed or renounced.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseAuthorityChanged {
+    pub account_history: Pubkey,
+    /// `None` when the close authority was renounced.
+    pub close_authority: Option<Pubkey>,
+    /// The proposed close authority of a two-step transfer, if any.
+    pub pending_close_authority: Option<Pubkey>,
+}
+
+/// The update authority of a history account was changed.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpdateAuthorityChanged {
+    pub account_history: Pubkey,
+    /// `None` when updates are permissionless.
+    pub update_authority: Option<Pubkey>,
+}
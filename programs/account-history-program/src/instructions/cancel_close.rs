@@ -0,0 +1,33 @@
+use anchor_lang::prelude::*;
+use crate::errors::AccountHistoryProgramError;
+use crate::events::CloseCancelled;
+use crate::state::AccountHistoryRaw;
+
+/// Reverse `initialize_account_close`, allowing updates to resume.
+#[derive(Accounts)]
+pub struct CancelAccountClose<'info> {
+    /// The sole authority capable of closing the history account.
+    close_authority: Signer<'info>,
+    /// CHECK: The history account whose close is being cancelled.
+    #[account(mut)]
+    account_state_history: UncheckedAccount<'info>,
+}
+
+impl<'info> CancelAccountClose<'info> {
+    pub fn process(&mut self) -> Result<()> {
+        let mut data = self.account_state_history.data.borrow_mut();
+        let act_history = AccountHistoryRaw::from_buffer(&mut data)?;
+        if self.close_authority.key() != act_history.header.close_authority {
+            return err!(AccountHistoryProgramError::NotCloseAuthority);
+        }
+        if act_history.header.close_initiated.is_none() {
+            return err!(AccountHistoryProgramError::CloseNotInitiated);
+        }
+        act_history.header.close_initiated = None;
+        emit!(CloseCancelled {
+            account_history: self.account_state_history.key(),
+            slot: Clock::get()?.slot,
+        });
+        Ok(())
+    }
+}
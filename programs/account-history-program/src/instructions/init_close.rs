@@ -1,5 +1,6 @@
 use anchor_lang::prelude::*;
 use crate::errors::AccountHistoryProgramError;
+use crate::events::CloseInitiated;
 use crate::state::AccountHistoryRaw;
 
 #[derive(Accounts)]
@@ -20,7 +21,13 @@ impl<'info> InitializeAccountClose<'info> {
         if self.close_authority.key() != act_history.header.close_authority {
             return err!(AccountHistoryProgramError::NotCloseAuthority);
         }
-        act_history.header.close_initiated = Some(Clock::get()?.slot.try_into().unwrap());
+        let slot = Clock::get()?.slot;
+        act_history.header.close_initiated = Some(slot.try_into().unwrap());
+        emit!(CloseInitiated {
+            account_history: self.account_state_history.key(),
+            slot,
+            resolvable_slot: slot + act_history.header.min_close_delay as u64,
+        });
         Ok(())
     }
 }
\ No newline at end of file
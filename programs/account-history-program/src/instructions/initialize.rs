@@ -1,6 +1,13 @@
 use anchor_lang::prelude::*;
 use crate::errors::AccountHistoryProgramError;
-use crate::state::AccountHistoryRaw;
+use crate::events::HistoryInitialized;
+use crate::instructions::update::emit_push;
+use crate::state::{AccountHistoryRaw, MAX_WATCHED_ACCOUNTS};
+use crate::presets::Preset;
+use crate::presets::pyth::{is_pyth_program, PythPriceSnapshot, PYTH_DATA_REGIONS};
+use crate::state::schema::{schema_fields_size, HistorySchema};
+use crate::state::options::HistoryOptions;
+use crate::state::regions::RegionLayout;
 
 /// Create a new historical data account, configured
 /// to watch a certain account.
@@ -28,35 +35,295 @@ pub struct InitializeAccountHistory<'info> {
 
 impl<'info> InitializeAccountHistory<'info> {
     pub fn process(&mut self, capacity: u32, min_slot_delay: u32, min_close_delay: u32, data_regions: Vec<(u32, u32)>, update_authority: Option<Pubkey>) -> Result<()> {
-        let mut data = self.account_state_history.data.borrow_mut();
-        let mut act_history = AccountHistoryRaw::init_from_buffer(&mut data)?;
-        act_history.header.associated_account = self.watched_account.key();
-        act_history.header.close_authority = self.payer.key();
-        act_history.header.update_authority = update_authority.unwrap_or(Pubkey::default());
-        act_history.header.capacity = capacity;
-        act_history.header.min_slot_delay = min_slot_delay;
-        act_history.header.min_close_delay = min_close_delay;
+        HistoryConfig {
+            capacity,
+            min_slot_delay,
+            min_close_delay,
+            data_regions: data_regions.into_iter().map(|(offset, len)| (0, offset, len)).collect(),
+            update_authority,
+            options: HistoryOptions::default(),
+            preset: Preset::None,
+        }.write(
+            &self.account_state_history,
+            self.payer.key(),
+            &[self.watched_account.to_account_info()],
+        )
+    }
+}
+
+/// Create a new historical data account watching a certain account,
+/// with the optional settings of `HistorySettings`.
+#[derive(Accounts)]
+#[instruction(capacity: u32, data_regions: Vec<(u32, u32)>, settings: HistorySettings)]
+pub struct InitializeAccountHistoryWithOptions<'info> {
+    /// Funds rent for the new history account.
+    #[account(mut)]
+    payer: Signer<'info>,
+    /// Signer and entropy seed for the account state history.
+    seed: Signer<'info>,
+    /// CHECK: The history account being created.
+    #[account(
+        init,
+        payer=payer,
+        space=AccountHistoryRaw::size_of(capacity, &data_regions) + schema_fields_size(settings.options.schema.len()),
+        seeds=[seed.key().as_ref()],
+        bump,
+    )]
+    account_state_history: UncheckedAccount<'info>,
+    /// CHECK: The account's data type is not read by this program
+    watched_account: UncheckedAccount<'info>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> InitializeAccountHistoryWithOptions<'info> {
+    pub fn process(&mut self, config: HistoryConfig) -> Result<()> {
+        config.write(
+            &self.account_state_history,
+            self.payer.key(),
+            &[self.watched_account.to_account_info()],
+        )
+    }
+}
+
+/// Create a new historical data account, configured to watch
+/// several accounts and record their state together in each element.
+/// The watched accounts after the first are passed as remaining accounts.
+#[derive(Accounts)]
+#[instruction(
+    capacity: u32,
+    data_regions: Vec<(u8, u32, u32)>,
+    min_slot_delay: Option<u32>,
+    min_close_delay: Option<u32>,
+    update_authority: Option<Pubkey>,
+    options: Option<HistoryOptions>,
+)]
+pub struct InitializeMultiAccountHistory<'info> {
+    /// Funds rent for the new history account.
+    #[account(mut)]
+    payer: Signer<'info>,
+    /// Signer and entropy seed for the account state history.
+    seed: Signer<'info>,
+    /// CHECK: The history account being created.
+    #[account(
+        init,
+        payer=payer,
+        space=AccountHistoryRaw::size_of_multi(capacity, &data_regions) + HistoryOptions::schema_size(&options),
+        seeds=[seed.key().as_ref()],
+        bump,
+    )]
+    account_state_history: UncheckedAccount<'info>,
+    /// CHECK: The account's data type is not read by this program
+    watched_account: UncheckedAccount<'info>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> InitializeMultiAccountHistory<'info> {
+    pub fn process(&mut self, config: HistoryConfig, additional_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        let watched_accounts: Vec<AccountInfo> = [self.watched_account.to_account_info()]
+            .into_iter()
+            .chain(additional_accounts.iter().cloned())
+            .collect();
+        config.write(
+            &self.account_state_history,
+            self.payer.key(),
+            &watched_accounts,
+        )
+    }
+}
+
+/// Create a new historical data account at an address derived from the first
+/// watched account, the creator and a label, so that it can be found without
+/// knowing a random seed. The watched accounts after the first are passed
+/// as remaining accounts.
+#[derive(Accounts)]
+#[instruction(capacity: u32, data_regions: Vec<(u8, u32, u32)>, label: String, settings: HistorySettings)]
+pub struct InitializeLabeledAccountHistory<'info> {
+    /// Funds rent for the new history account, and becomes its close authority.
+    #[account(mut)]
+    creator: Signer<'info>,
+    /// CHECK: The history account being created.
+    #[account(
+        init,
+        payer=creator,
+        space=AccountHistoryRaw::size_of_multi(capacity, &data_regions) + schema_fields_size(settings.options.schema.len()),
+        seeds=[watched_account.key().as_ref(), creator.key().as_ref(), label.as_bytes()],
+        bump,
+    )]
+    account_state_history: UncheckedAccount<'info>,
+    /// CHECK: The account's data type is not read by this program
+    watched_account: UncheckedAccount<'info>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> InitializeLabeledAccountHistory<'info> {
+    pub fn process(&mut self, config: HistoryConfig, additional_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        let watched_accounts: Vec<AccountInfo> = [self.watched_account.to_account_info()]
+            .into_iter()
+            .chain(additional_accounts.iter().cloned())
+            .collect();
+        config.write(
+            &self.account_state_history,
+            self.creator.key(),
+            &watched_accounts,
+        )
+    }
+}
+
+/// Create a new historical data account recording the aggregate price of a Pyth price account.
+/// The data regions are those of `PythPriceSnapshot`, and updates are rejected
+/// unless the price is trading and recent.
+#[derive(Accounts)]
+#[instruction(capacity: u32)]
+pub struct InitializePythPriceHistory<'info> {
+    /// Funds rent for the new history account.
+    #[account(mut)]
+    payer: Signer<'info>,
+    /// Signer and entropy seed for the account state history.
+    seed: Signer<'info>,
+    /// CHECK: The history account being created.
+    #[account(
+        init,
+        payer=payer,
+        space=AccountHistoryRaw::size_of_multi(capacity, &PYTH_DATA_REGIONS)
+            + schema_fields_size(PythPriceSnapshot::schema().len()),
+        seeds=[seed.key().as_ref()],
+        bump,
+    )]
+    account_state_history: UncheckedAccount<'info>,
+    /// CHECK: Owned by a Pyth oracle program, and validated as a price account on every update
+    #[account(
+        constraint = is_pyth_program(price_account.owner) @ AccountHistoryProgramError::InvalidPriceAccount,
+    )]
+    price_account: UncheckedAccount<'info>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> InitializePythPriceHistory<'info> {
+    pub fn process(&mut self, config: HistoryConfig) -> Result<()> {
+        config.write(
+            &self.account_state_history,
+            self.payer.key(),
+            &[self.price_account.to_account_info()],
+        )
+    }
+}
+
+/// The optional arguments of the instructions initializing a history account.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct HistorySettings {
+    /// Minimum number of slots between two updates, 1 if `None`.
+    pub min_slot_delay: Option<u32>,
+    /// Minimum number of slots between initiating and resolving a close, 0 if `None`.
+    pub min_close_delay: Option<u32>,
+    /// The only signer allowed to update the history, anyone if `None`.
+    pub update_authority: Option<Pubkey>,
+    pub options: HistoryOptions,
+}
+
+impl HistorySettings {
+    /// The configuration of a history account recording `data_regions`, without a preset.
+    pub fn config(self, capacity: u32, data_regions: Vec<(u8, u32, u32)>) -> HistoryConfig {
+        HistoryConfig {
+            capacity,
+            min_slot_delay: self.min_slot_delay.unwrap_or(1),
+            min_close_delay: self.min_close_delay.unwrap_or(0),
+            data_regions,
+            update_authority: self.update_authority,
+            options: self.options,
+            preset: Preset::None,
+        }
+    }
+}
+
+/// Configuration shared by each way of initializing a history account.
+pub struct HistoryConfig {
+    pub capacity: u32,
+    pub min_slot_delay: u32,
+    pub min_close_delay: u32,
+    /// Tuples of (watched account index, offset, length).
+    pub data_regions: Vec<(u8, u32, u32)>,
+    pub update_authority: Option<Pubkey>,
+    pub options: HistoryOptions,
+    pub preset: Preset,
+}
+
+impl HistoryConfig {
+    /// Write the header of a newly created history account,
+    /// and push an initial snapshot of the watched accounts.
+    pub fn write(self, account_state_history: &AccountInfo, close_authority: Pubkey, watched_accounts: &[AccountInfo]) -> Result<()> {
+        if self.capacity == 0 {
+            return err!(AccountHistoryProgramError::InvalidCapacity);
+        }
+        if watched_accounts.len() > MAX_WATCHED_ACCOUNTS {
+            return err!(AccountHistoryProgramError::TooManyWatchedAccounts);
+        }
+        let layout = RegionLayout::new(&self.data_regions, watched_accounts.len())?;
+        layout.check_bounds(&watched_accounts.iter().map(|a| a.data_len()).collect::<Vec<_>>())?;
+        let mut data = account_state_history.data.borrow_mut();
+        let mut act_history = AccountHistoryRaw::init_from_buffer(
+            &mut data,
+            layout.regions().len(),
+            &self.options.schema,
+            layout.element_size(),
+        )?;
+        act_history.regions.copy_from_slice(layout.regions());
+        for (i, account) in watched_accounts.iter().enumerate() {
+            act_history.header.watched_accounts[i] = account.key();
+            if self.options.pin_owners {
+                act_history.header.watched_account_owners[i] = *account.owner;
+            }
+        }
+        act_history.header.num_watched_accounts = watched_accounts.len() as u8;
+        act_history.header.close_authority = close_authority;
+        act_history.header.update_authority = self.update_authority.unwrap_or_default();
+        act_history.header.capacity = self.capacity;
+        act_history.header.min_slot_delay = self.min_slot_delay;
+        act_history.header.min_close_delay = self.min_close_delay;
+        act_history.header.shrink_policy = self.options.shrink_policy as u8;
+        act_history.header.unchanged_policy = self.options.unchanged_policy as u8;
+        act_history.header.preset = self.preset.tag();
+        act_history.header.preset_param = self.preset.param();
         // 8 bytes for slot, then data.
-        act_history.header.data_element_size = 8u32 +
-            data_regions.iter().map(|(_, i)| *i).sum::<u32>();
-        act_history.header.data_regions = sanitize_data_regions(&data_regions)?;
-        act_history.push(
-            &self.watched_account.data.borrow(),
+        act_history.header.data_element_size = layout.element_size();
+        emit!(HistoryInitialized {
+            account_history: account_state_history.key(),
+            watched_accounts: act_history.watched_accounts().to_vec(),
+            close_authority,
+            update_authority: self.update_authority,
+            capacity: self.capacity,
+            data_element_size: layout.element_size(),
+        });
+        let watched_data: Vec<_> = watched_accounts.iter().map(|a| a.data.borrow()).collect();
+        let outcome = act_history.push_from_accounts(
+            &watched_data.iter().map(|d| &***d).collect::<Vec<&[u8]>>(),
             Clock::get()?.slot,
         )?;
+        emit_push(account_state_history.key(), &act_history, outcome);
         Ok(())
     }
 }
 
-/// Ensure the data regions are non-zero in length,
-pub fn sanitize_data_regions(pairs: &[(u32, u32)]) -> Result<[u32; 16]> {
-    let mut loc = [0u32; 16];
-    for (i, pair) in pairs.iter().enumerate() {
-        if pair.1 == 0 {
-            return err!(AccountHistoryProgramError::InvalidDataRegions);
-        }
-        loc[i] = pair.0;
-        loc[i+1] = pair.1;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_capacity() {
+        let (key, owner) = (Pubkey::new_unique(), crate::ID);
+        let (mut lamports, mut data) = (0, vec![0u8; AccountHistoryRaw::size_of(0, &[(0, 8)])]);
+        let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        let config = HistoryConfig {
+            capacity: 0,
+            min_slot_delay: 1,
+            min_close_delay: 0,
+            data_regions: vec![(0, 0, 8)],
+            update_authority: None,
+            options: HistoryOptions::default(),
+            preset: Preset::None,
+        };
+        assert_eq!(
+            config.write(&account, key, std::slice::from_ref(&account)).unwrap_err(),
+            AccountHistoryProgramError::InvalidCapacity.into(),
+        );
     }
-    Ok(loc)
 }
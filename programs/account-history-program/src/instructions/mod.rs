@@ -1,9 +1,21 @@
 pub mod initialize;
 pub mod update;
 pub mod init_close;
+pub mod cancel_close;
 pub mod resolve_account_close;
+pub mod resize;
+pub mod set_close_authority;
+pub mod set_update_authority;
+pub mod query;
+pub mod reward_vault;
 
 pub use initialize::*;
 pub use update::*;
 pub use init_close::*;
+pub use cancel_close::*;
 pub use resolve_account_close::*;
+pub use resize::*;
+pub use set_close_authority::*;
+pub use set_update_authority::*;
+pub use query::*;
+pub use reward_vault::*;
@@ -0,0 +1,162 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::clock::Slot;
+use anchor_lang::solana_program::program::MAX_RETURN_DATA;
+use crate::errors::AccountHistoryProgramError;
+use crate::state::AccountHistoryRaw;
+use crate::state::schema::FieldType;
+use crate::stats::{compute_raw, Stats, Window};
+
+/// Read a history account, and return the answer to a query as return data.
+/// Useful to programs that cannot link this crate, and to clients simulating a read.
+#[derive(Accounts)]
+pub struct QueryAccountHistory<'info> {
+    /// CHECK: The history account being queried.
+    /// Must be owned by this program, so that its data can be trusted.
+    #[account(owner = crate::ID)]
+    account_state_history: UncheckedAccount<'info>,
+}
+
+/// A question about the elements of a history account.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryQuery {
+    /// The most recent element.
+    Latest,
+    /// The most recent element recorded at or before a slot.
+    AtOrBeforeSlot(Slot),
+    /// The oldest element recorded at or after a slot.
+    AtOrAfterSlot(Slot),
+    /// Statistics of a numeric field over a window of elements.
+    Stats { field: Field, window: Window },
+}
+
+/// The answer to a `HistoryQuery`.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq)]
+pub enum QueryResult {
+    /// The raw bytes of the matching element, slot included, if any.
+    Entry(Option<Vec<u8>>),
+    /// Statistics over the window, if it has any elements.
+    Stats(Option<Stats>),
+}
+
+/// A little-endian numeric field within each element.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    /// Byte offset of the field within each element, counting the 8 bytes of the slot.
+    pub offset: u32,
+    /// Must be a numeric type.
+    pub field_type: FieldType,
+}
+
+impl Field {
+    /// Read the field from an element as a float, or `None` if its type is not numeric.
+    /// The element must be long enough to hold the field.
+    pub fn read(&self, entry: &[u8]) -> Option<f64> {
+        let bytes = &entry[self.offset as usize..][..self.field_type.size() as usize];
+        Some(match self.field_type {
+            FieldType::U8 => bytes[0] as f64,
+            FieldType::U16 => u16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            FieldType::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            FieldType::U64 => u64::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            FieldType::U128 => u128::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            FieldType::I8 => bytes[0] as i8 as f64,
+            FieldType::I16 => i16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            FieldType::I32 => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            FieldType::I64 => i64::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            FieldType::I128 => i128::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            FieldType::Bool | FieldType::Pubkey | FieldType::Bytes(_) => return None,
+        })
+    }
+}
+
+impl<'info> QueryAccountHistory<'info> {
+    pub fn process(&self, query: HistoryQuery) -> Result<QueryResult> {
+        // The history is not modified, the mutable borrow is only needed to parse it.
+        let mut data = self.account_state_history.data.borrow_mut();
+        let account_history = AccountHistoryRaw::from_buffer(&mut data)?;
+        run_query(&account_history, query)
+    }
+}
+
+/// Answer a query about a history account.
+/// Fails if the serialized answer does not fit in return data, which happens
+/// for `Entry` answers when elements are close to `MAX_RETURN_DATA` bytes.
+pub fn run_query(account_history: &AccountHistoryRaw, query: HistoryQuery) -> Result<QueryResult> {
+    let entry = match query {
+        HistoryQuery::Latest => Some(account_history.most_recent_entry()),
+        HistoryQuery::AtOrBeforeSlot(slot) => account_history.at_or_before_slot(slot),
+        HistoryQuery::AtOrAfterSlot(slot) => account_history.at_or_after_slot(slot),
+        HistoryQuery::Stats { field, window } => {
+            if !field.field_type.is_numeric()
+                || field.offset as u64 + field.field_type.size() as u64 > account_history.header().data_element_size as u64 {
+                return err!(AccountHistoryProgramError::InvalidQueryField);
+            }
+            return Ok(QueryResult::Stats(compute_raw(account_history, window, |entry| field.read(entry).unwrap())));
+        },
+    };
+    let result = QueryResult::Entry(entry.map(|entry| entry.to_vec()));
+    if result.try_to_vec()?.len() > MAX_RETURN_DATA {
+        return err!(AccountHistoryProgramError::QueryResultTooLarge);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query() {
+        let mut data = vec![0u8; AccountHistoryRaw::size_of(4, &[(0, 8)])];
+        let mut history = AccountHistoryRaw::init_from_buffer(&mut data, 1, &[], 16).unwrap();
+        history.regions[0].len = 8;
+        history.header.capacity = 4;
+        history.header.data_element_size = 16;
+        for (slot, price) in [(10u64, -5i64), (20, 15), (30, 5)] {
+            history.push(&price.to_le_bytes(), slot).unwrap();
+        }
+        let entry = |slot: u64, price: i64| Some([slot.to_le_bytes(), price.to_le_bytes()].concat());
+
+        assert_eq!(run_query(&history, HistoryQuery::Latest).unwrap(), QueryResult::Entry(entry(30, 5)));
+        assert_eq!(run_query(&history, HistoryQuery::AtOrBeforeSlot(25)).unwrap(), QueryResult::Entry(entry(20, 15)));
+        assert_eq!(run_query(&history, HistoryQuery::AtOrAfterSlot(25)).unwrap(), QueryResult::Entry(entry(30, 5)));
+        assert_eq!(run_query(&history, HistoryQuery::AtOrAfterSlot(31)).unwrap(), QueryResult::Entry(None));
+
+        let price = Field { offset: 8, field_type: FieldType::I64 };
+        let stats = match run_query(&history, HistoryQuery::Stats { field: price, window: Window::Last(3) }).unwrap() {
+            QueryResult::Stats(Some(stats)) => stats,
+            result => panic!("unexpected result {:?}", result),
+        };
+        assert_eq!((stats.min, stats.max, stats.mean), (-5.0, 15.0, 5.0));
+        assert_eq!(stats.twap, 5.0);
+
+        let too_far = Field { offset: 9, field_type: FieldType::I64 };
+        let not_numeric = Field { offset: 8, field_type: FieldType::Bool };
+        for field in [too_far, not_numeric] {
+            assert_eq!(
+                run_query(&history, HistoryQuery::Stats { field, window: Window::Last(3) }).unwrap_err(),
+                AccountHistoryProgramError::InvalidQueryField.into(),
+            );
+        }
+    }
+
+    #[test]
+    fn large_elements() {
+        // The tags of the result and the option, the length of the bytes and the slot take 14 bytes
+        for (data_len, fits) in [(MAX_RETURN_DATA - 14, true), (MAX_RETURN_DATA - 13, false)] {
+            let mut data = vec![0u8; AccountHistoryRaw::size_of(2, &[(0, data_len as u32)])];
+            let mut history = AccountHistoryRaw::init_from_buffer(&mut data, 1, &[], data_len as u32 + 8).unwrap();
+            history.regions[0].len = data_len as u32;
+            history.header.capacity = 2;
+            history.header.data_element_size = data_len as u32 + 8;
+            history.push(&vec![7u8; data_len], 10).unwrap();
+
+            let result = run_query(&history, HistoryQuery::Latest);
+            if fits {
+                assert_eq!(result.unwrap().try_to_vec().unwrap().len(), MAX_RETURN_DATA);
+            } else {
+                assert_eq!(result.unwrap_err(), AccountHistoryProgramError::QueryResultTooLarge.into());
+            }
+            assert_eq!(run_query(&history, HistoryQuery::AtOrAfterSlot(11)).unwrap(), QueryResult::Entry(None));
+        }
+    }
+}
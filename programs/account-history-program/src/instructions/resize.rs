@@ -0,0 +1,77 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::MAX_PERMITTED_DATA_INCREASE;
+use anchor_lang::system_program::{transfer, Transfer};
+use crate::errors::AccountHistoryProgramError;
+use crate::events::HistoryResized;
+use crate::state::AccountHistoryRaw;
+
+/// Grow or shrink the capacity of a history account in place,
+/// preserving as many of the most recent elements as will fit.
+#[derive(Accounts)]
+pub struct ResizeAccountHistory<'info> {
+    /// The sole authority capable of resizing the history account.
+    /// Funds any additional rent, and receives any refunded rent.
+    #[account(mut)]
+    close_authority: Signer<'info>,
+    /// CHECK: The history account being resized.
+    #[account(mut)]
+    account_state_history: UncheckedAccount<'info>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> ResizeAccountHistory<'info> {
+    pub fn process(&mut self, capacity: u32) -> Result<()> {
+        if capacity == 0 {
+            return err!(AccountHistoryProgramError::InvalidCapacity);
+        }
+        let new_size = {
+            let mut data = self.account_state_history.data.borrow_mut();
+            let mut act_history = AccountHistoryRaw::from_buffer(&mut data)?;
+            if self.close_authority.key() != act_history.header.close_authority {
+                return err!(AccountHistoryProgramError::NotCloseAuthority);
+            }
+            if act_history.header.close_initiated.is_some() {
+                return err!(AccountHistoryProgramError::AccountBeingClosed);
+            }
+            let new_size = act_history.size_with_capacity(capacity);
+            if new_size > act_history.size_with_capacity(act_history.capacity() as u32)
+                + MAX_PERMITTED_DATA_INCREASE {
+                return err!(AccountHistoryProgramError::ResizeTooLarge);
+            }
+            act_history.begin_resize(capacity);
+            new_size
+        };
+        self.account_state_history.realloc(new_size, true)?;
+        {
+            let mut data = self.account_state_history.data.borrow_mut();
+            let mut act_history = AccountHistoryRaw::from_buffer(&mut data)?;
+            act_history.end_resize(capacity);
+            emit!(HistoryResized {
+                account_history: self.account_state_history.key(),
+                capacity,
+                len: act_history.len() as u32,
+            });
+        }
+        // Top up or refund rent
+        let rent_exempt_lamports = Rent::get()?.minimum_balance(new_size);
+        let current_lamports = self.account_state_history.lamports();
+        if rent_exempt_lamports > current_lamports {
+            transfer(
+                CpiContext::new(
+                    self.system_program.to_account_info(),
+                    Transfer {
+                        from: self.close_authority.to_account_info(),
+                        to: self.account_state_history.to_account_info(),
+                    },
+                ),
+                rent_exempt_lamports - current_lamports,
+            )?;
+        } else if current_lamports > rent_exempt_lamports {
+            let dest_starting_lamports = self.close_authority.lamports();
+            **self.close_authority.lamports.borrow_mut() = dest_starting_lamports
+                .checked_add(current_lamports - rent_exempt_lamports).unwrap();
+            **self.account_state_history.lamports.borrow_mut() = rent_exempt_lamports;
+        }
+        Ok(())
+    }
+}
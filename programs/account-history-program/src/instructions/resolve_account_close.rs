@@ -1,6 +1,10 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount};
 use crate::errors::AccountHistoryProgramError;
+use crate::events::CloseResolved;
+use crate::instructions::reward_vault::pay_reward;
 use crate::state::AccountHistoryRaw;
+use crate::state::reward_vault::{RewardVault, REWARD_VAULT_SEED};
 
 #[derive(Accounts)]
 pub struct ResolveAccountClose<'info> {
@@ -13,6 +17,17 @@ pub struct ResolveAccountClose<'info> {
     /// CHECK: The history account being closed.
     #[account(mut)]
     account_state_history: UncheckedAccount<'info>,
+    /// The reward vault of the history account, if it has one.
+    /// Its lamports are swept to the rent recipient.
+    #[account(mut)]
+    reward_vault: Option<Account<'info, RewardVault>>,
+    /// The token account of a token reward vault, closed to the rent recipient.
+    #[account(mut)]
+    vault_tokens: Option<Account<'info, TokenAccount>>,
+    /// Receives the remaining tokens of a token reward vault.
+    #[account(mut)]
+    recipient_tokens: Option<Account<'info, TokenAccount>>,
+    token_program: Option<Program<'info, Token>>,
 }
 
 impl<'info> ResolveAccountClose<'info> {
@@ -34,15 +49,54 @@ impl<'info> ResolveAccountClose<'info> {
                 return err!(AccountHistoryProgramError::CloseNotInitiated);
             }
         }
+        if act_history.header.has_reward_vault() {
+            self.sweep_reward_vault()?;
+        }
         // Close account (lamports, owner, realloc)
+        let lamports = self.account_state_history.lamports();
         let dest_starting_lamports = self.rent_recipient.lamports();
         **self.rent_recipient.lamports.borrow_mut() =
-            dest_starting_lamports.checked_add(self.account_state_history.lamports()).unwrap();
+            dest_starting_lamports.checked_add(lamports).unwrap();
         **self.account_state_history.lamports.borrow_mut() = 0;
 
         self.account_state_history.assign(&System::id());
         self.account_state_history.realloc(0, false)?;
 
+        emit!(CloseResolved {
+            account_history: self.account_state_history.key(),
+            rent_recipient: self.rent_recipient.key(),
+            lamports,
+        });
         Ok(())
     }
+
+    /// Send the remaining rewards to the rent recipient, and close the reward vault.
+    fn sweep_reward_vault(&self) -> Result<()> {
+        let reward_vault = match &self.reward_vault {
+            Some(reward_vault) if reward_vault.account_history == self.account_state_history.key() => reward_vault,
+            _ => return err!(AccountHistoryProgramError::RewardVaultNotSwept),
+        };
+        if reward_vault.mint().is_some() {
+            let vault_tokens = self.vault_tokens.as_ref()
+                .ok_or(AccountHistoryProgramError::InvalidRewardTokenAccounts)?;
+            pay_reward(
+                reward_vault,
+                &self.rent_recipient.to_account_info(),
+                Some(vault_tokens),
+                self.recipient_tokens.as_ref(),
+                self.token_program.as_ref(),
+                vault_tokens.amount,
+            )?;
+            token::close_account(CpiContext::new_with_signer(
+                self.token_program.as_ref().unwrap().to_account_info(),
+                token::CloseAccount {
+                    account: vault_tokens.to_account_info(),
+                    destination: self.rent_recipient.to_account_info(),
+                    authority: reward_vault.to_account_info(),
+                },
+                &[&[REWARD_VAULT_SEED, reward_vault.account_history.as_ref(), &[reward_vault.bump]]],
+            ))?;
+        }
+        reward_vault.close(self.rent_recipient.to_account_info())
+    }
 }
\ No newline at end of file
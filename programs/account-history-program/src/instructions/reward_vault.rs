@@ -0,0 +1,364 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::clock::Slot;
+use anchor_lang::system_program;
+use anchor_spl::token::{self, Mint, Token, TokenAccount};
+use crate::errors::AccountHistoryProgramError;
+use crate::events::{RewardPaid, RewardVaultInitialized, RewardsDeposited, RewardsWithdrawn};
+use crate::state::{AccountHistoryRaw, PushOutcome};
+use crate::state::reward_vault::{RewardVault, REWARD_VAULT_SEED, REWARD_VAULT_TOKENS_SEED};
+
+/// Attach a reward vault paying a bounty in lamports for each update of a history account.
+#[derive(Accounts)]
+pub struct InitializeRewardVault<'info> {
+    /// The close authority of the history account. Funds rent for the vault.
+    #[account(mut)]
+    close_authority: Signer<'info>,
+    /// CHECK: The history account being rewarded.
+    #[account(mut, owner = crate::ID)]
+    account_state_history: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer=close_authority,
+        space=RewardVault::LEN,
+        seeds=[REWARD_VAULT_SEED, account_state_history.key().as_ref()],
+        bump,
+    )]
+    reward_vault: Account<'info, RewardVault>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> InitializeRewardVault<'info> {
+    pub fn process(&mut self, bounty: u64, bump: u8) -> Result<()> {
+        attach_reward_vault(&self.account_state_history, &self.close_authority.key())?;
+        self.reward_vault.set_inner(RewardVault {
+            account_history: self.account_state_history.key(),
+            mint: Pubkey::default(),
+            bounty,
+            bump,
+        });
+        emit_reward_vault_initialized(&self.reward_vault);
+        Ok(())
+    }
+}
+
+/// Attach a reward vault paying a bounty in SPL tokens of `mint` for each update of a history account.
+#[derive(Accounts)]
+pub struct InitializeTokenRewardVault<'info> {
+    /// The close authority of the history account. Funds rent for the vault.
+    #[account(mut)]
+    close_authority: Signer<'info>,
+    /// CHECK: The history account being rewarded.
+    #[account(mut, owner = crate::ID)]
+    account_state_history: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer=close_authority,
+        space=RewardVault::LEN,
+        seeds=[REWARD_VAULT_SEED, account_state_history.key().as_ref()],
+        bump,
+    )]
+    reward_vault: Account<'info, RewardVault>,
+    mint: Account<'info, Mint>,
+    /// Holds the tokens paid as bounties.
+    #[account(
+        init,
+        payer=close_authority,
+        token::mint=mint,
+        token::authority=reward_vault,
+        seeds=[REWARD_VAULT_TOKENS_SEED, account_state_history.key().as_ref()],
+        bump,
+    )]
+    vault_tokens: Account<'info, TokenAccount>,
+    token_program: Program<'info, Token>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> InitializeTokenRewardVault<'info> {
+    pub fn process(&mut self, bounty: u64, bump: u8) -> Result<()> {
+        attach_reward_vault(&self.account_state_history, &self.close_authority.key())?;
+        self.reward_vault.set_inner(RewardVault {
+            account_history: self.account_state_history.key(),
+            mint: self.mint.key(),
+            bounty,
+            bump,
+        });
+        emit_reward_vault_initialized(&self.reward_vault);
+        Ok(())
+    }
+}
+
+/// Emit the event of a newly attached reward vault.
+fn emit_reward_vault_initialized(reward_vault: &Account<RewardVault>) {
+    emit!(RewardVaultInitialized {
+        account_history: reward_vault.account_history,
+        reward_vault: reward_vault.key(),
+        mint: reward_vault.mint(),
+        bounty: reward_vault.bounty,
+    });
+}
+
+/// Check the close authority of a history account, and flag it as having a reward vault.
+/// Without a `min_slot_delay`, a keeper could drain the vault with many updates in a single slot.
+fn attach_reward_vault(account_state_history: &AccountInfo, close_authority: &Pubkey) -> Result<()> {
+    let mut data = account_state_history.data.borrow_mut();
+    let act_history = AccountHistoryRaw::from_buffer(&mut data)?;
+    if *close_authority != act_history.header.close_authority {
+        return err!(AccountHistoryProgramError::NotCloseAuthority);
+    }
+    if act_history.header.min_slot_delay == 0 {
+        return err!(AccountHistoryProgramError::RewardVaultRequiresSlotDelay);
+    }
+    act_history.header.has_reward_vault = 1;
+    Ok(())
+}
+
+/// Add rewards to the vault of a history account.
+/// The token accounts are only needed for token reward vaults.
+#[derive(Accounts)]
+pub struct DepositRewards<'info> {
+    #[account(mut)]
+    depositor: Signer<'info>,
+    #[account(mut)]
+    reward_vault: Account<'info, RewardVault>,
+    #[account(mut)]
+    vault_tokens: Option<Account<'info, TokenAccount>>,
+    /// The depositor's token account.
+    #[account(mut)]
+    depositor_tokens: Option<Account<'info, TokenAccount>>,
+    token_program: Option<Program<'info, Token>>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> DepositRewards<'info> {
+    pub fn process(&mut self, amount: u64) -> Result<()> {
+        self.transfer(amount)?;
+        emit!(RewardsDeposited {
+            account_history: self.reward_vault.account_history,
+            depositor: self.depositor.key(),
+            mint: self.reward_vault.mint(),
+            amount,
+        });
+        Ok(())
+    }
+
+    fn transfer(&self, amount: u64) -> Result<()> {
+        match self.reward_vault.mint() {
+            None => system_program::transfer(
+                CpiContext::new(
+                    self.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: self.depositor.to_account_info(),
+                        to: self.reward_vault.to_account_info(),
+                    },
+                ),
+                amount,
+            ),
+            Some(_) => {
+                let (vault_tokens, depositor_tokens, token_program) = match (
+                    &self.vault_tokens,
+                    &self.depositor_tokens,
+                    &self.token_program,
+                ) {
+                    (Some(vault_tokens), Some(depositor_tokens), Some(token_program)) => {
+                        (vault_tokens, depositor_tokens, token_program)
+                    },
+                    _ => return err!(AccountHistoryProgramError::InvalidRewardTokenAccounts),
+                };
+                check_vault_tokens(&self.reward_vault, vault_tokens)?;
+                token::transfer(
+                    CpiContext::new(
+                        token_program.to_account_info(),
+                        token::Transfer {
+                            from: depositor_tokens.to_account_info(),
+                            to: vault_tokens.to_account_info(),
+                            authority: self.depositor.to_account_info(),
+                        },
+                    ),
+                    amount,
+                )
+            },
+        }
+    }
+}
+
+/// Take rewards out of the vault of a history account.
+/// Lamports are sent to the close authority, and tokens to `recipient_tokens`.
+#[derive(Accounts)]
+pub struct WithdrawRewards<'info> {
+    /// The close authority of the history account.
+    #[account(mut)]
+    close_authority: Signer<'info>,
+    /// CHECK: The history account being rewarded.
+    #[account(owner = crate::ID)]
+    account_state_history: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds=[REWARD_VAULT_SEED, account_state_history.key().as_ref()],
+        bump=reward_vault.bump,
+    )]
+    reward_vault: Account<'info, RewardVault>,
+    #[account(mut)]
+    vault_tokens: Option<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    recipient_tokens: Option<Account<'info, TokenAccount>>,
+    token_program: Option<Program<'info, Token>>,
+}
+
+impl<'info> WithdrawRewards<'info> {
+    pub fn process(&mut self, amount: u64) -> Result<()> {
+        {
+            let mut data = self.account_state_history.data.borrow_mut();
+            let act_history = AccountHistoryRaw::from_buffer(&mut data)?;
+            if self.close_authority.key() != act_history.header.close_authority {
+                return err!(AccountHistoryProgramError::NotCloseAuthority);
+            }
+        }
+        let paid = pay_reward(
+            &self.reward_vault,
+            &self.close_authority.to_account_info(),
+            self.vault_tokens.as_ref(),
+            self.recipient_tokens.as_ref(),
+            self.token_program.as_ref(),
+            amount,
+        )?;
+        if !paid {
+            return err!(AccountHistoryProgramError::InsufficientRewards);
+        }
+        emit!(RewardsWithdrawn {
+            account_history: self.account_state_history.key(),
+            recipient: self.close_authority.key(),
+            mint: self.reward_vault.mint(),
+            amount,
+        });
+        Ok(())
+    }
+}
+
+/// Pay the signer of an update the bounty of a reward vault, if the update recorded account data
+/// at a later slot than `previous_slot` and the vault holds enough rewards.
+/// Token bounties are paid to `recipient_tokens`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn pay_update_reward<'info>(
+    account_state_history: &AccountInfo<'info>,
+    signer: &AccountInfo<'info>,
+    reward_vault: &Account<'info, RewardVault>,
+    vault_tokens: Option<&Account<'info, TokenAccount>>,
+    recipient_tokens: Option<&Account<'info, TokenAccount>>,
+    token_program: Option<&Program<'info, Token>>,
+    outcome: PushOutcome,
+    previous_slot: Slot,
+    slot: Slot,
+) -> Result<()> {
+    // Only updates that record account data are rewarded, not tombstones,
+    // which could be pushed repeatedly for a closed watched account,
+    // and at most one update per slot is rewarded
+    if outcome != PushOutcome::Pushed || slot <= previous_slot {
+        return Ok(());
+    }
+    let paid = pay_reward(
+        reward_vault,
+        signer,
+        vault_tokens,
+        recipient_tokens,
+        token_program,
+        reward_vault.bounty,
+    )?;
+    if paid {
+        emit!(RewardPaid {
+            account_history: account_state_history.key(),
+            recipient: signer.key(),
+            mint: reward_vault.mint(),
+            amount: reward_vault.bounty,
+        });
+    }
+    Ok(())
+}
+
+/// Ensure a token account is the token vault of a reward vault.
+fn check_vault_tokens(reward_vault: &Account<RewardVault>, vault_tokens: &Account<TokenAccount>) -> Result<()> {
+    let (expected, _) = Pubkey::find_program_address(
+        &[REWARD_VAULT_TOKENS_SEED, reward_vault.account_history.as_ref()],
+        &crate::ID,
+    );
+    if vault_tokens.key() != expected {
+        return err!(AccountHistoryProgramError::InvalidRewardTokenAccounts);
+    }
+    Ok(())
+}
+
+/// Pay `amount` out of a reward vault, in lamports to `recipient`, or in tokens to `recipient_tokens`.
+/// Returns `false`, paying nothing, if the vault holds less than `amount`.
+pub(crate) fn pay_reward<'info>(
+    reward_vault: &Account<'info, RewardVault>,
+    recipient: &AccountInfo<'info>,
+    vault_tokens: Option<&Account<'info, TokenAccount>>,
+    recipient_tokens: Option<&Account<'info, TokenAccount>>,
+    token_program: Option<&Program<'info, Token>>,
+    amount: u64,
+) -> Result<bool> {
+    match reward_vault.mint() {
+        None => {
+            // The vault must stay rent exempt
+            let vault = reward_vault.to_account_info();
+            let available = vault.lamports()
+                .saturating_sub(Rent::get()?.minimum_balance(vault.data_len()));
+            if available < amount {
+                return Ok(false);
+            }
+            **vault.lamports.borrow_mut() -= amount;
+            **recipient.lamports.borrow_mut() = recipient.lamports().checked_add(amount).unwrap();
+            Ok(true)
+        },
+        Some(_) => {
+            let (vault_tokens, recipient_tokens, token_program) = match (vault_tokens, recipient_tokens, token_program) {
+                (Some(vault_tokens), Some(recipient_tokens), Some(token_program)) => {
+                    (vault_tokens, recipient_tokens, token_program)
+                },
+                _ => return err!(AccountHistoryProgramError::InvalidRewardTokenAccounts),
+            };
+            check_vault_tokens(reward_vault, vault_tokens)?;
+            if vault_tokens.amount < amount {
+                return Ok(false);
+            }
+            token::transfer(
+                CpiContext::new_with_signer(
+                    token_program.to_account_info(),
+                    token::Transfer {
+                        from: vault_tokens.to_account_info(),
+                        to: recipient_tokens.to_account_info(),
+                        authority: reward_vault.to_account_info(),
+                    },
+                    &[&[REWARD_VAULT_SEED, reward_vault.account_history.as_ref(), &[reward_vault.bump]]],
+                ),
+                amount,
+            )?;
+            Ok(true)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attach_requires_slot_delay() {
+        let (key, close_authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = vec![0u8; AccountHistoryRaw::size_of(4, &[(0, 8)])];
+        let history = AccountHistoryRaw::init_from_buffer(&mut data, 1, &[], 16).unwrap();
+        history.header.close_authority = close_authority;
+        let mut lamports = 0;
+        let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &crate::ID, false, 0);
+        assert_eq!(
+            attach_reward_vault(&account, &close_authority).unwrap_err(),
+            AccountHistoryProgramError::RewardVaultRequiresSlotDelay.into(),
+        );
+        AccountHistoryRaw::from_buffer(&mut account.data.borrow_mut()).unwrap().header.min_slot_delay = 1;
+        assert_eq!(
+            attach_reward_vault(&account, &Pubkey::new_unique()).unwrap_err(),
+            AccountHistoryProgramError::NotCloseAuthority.into(),
+        );
+        attach_reward_vault(&account, &close_authority).unwrap();
+        assert!(AccountHistoryRaw::from_buffer(&mut account.data.borrow_mut()).unwrap().header.has_reward_vault());
+    }
+}
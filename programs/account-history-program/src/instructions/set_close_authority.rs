@@ -0,0 +1,102 @@
+use anchor_lang::prelude::*;
+use crate::errors::AccountHistoryProgramError;
+use crate::events::CloseAuthorityChanged;
+use crate::state::AccountHistoryRaw;
+
+/// Transfer the close authority of a history account, either immediately,
+/// or by proposing a new close authority who must then accept the transfer.
+#[derive(Accounts)]
+pub struct SetCloseAuthority<'info> {
+    /// The current close authority.
+    close_authority: Signer<'info>,
+    /// CHECK: The history account being modified.
+    #[account(mut)]
+    account_state_history: UncheckedAccount<'info>,
+}
+
+impl<'info> SetCloseAuthority<'info> {
+    pub fn process(&mut self, new_close_authority: Pubkey, two_step: bool) -> Result<()> {
+        let mut data = self.account_state_history.data.borrow_mut();
+        let act_history = AccountHistoryRaw::from_buffer(&mut data)?;
+        if self.close_authority.key() != act_history.header.close_authority {
+            return err!(AccountHistoryProgramError::NotCloseAuthority);
+        }
+        if new_close_authority == Pubkey::default() {
+            return err!(AccountHistoryProgramError::InvalidCloseAuthority);
+        }
+        if two_step {
+            act_history.header.pending_close_authority = new_close_authority;
+        } else {
+            act_history.header.close_authority = new_close_authority;
+            act_history.header.pending_close_authority = Pubkey::default();
+        }
+        emit!(CloseAuthorityChanged {
+            account_history: self.account_state_history.key(),
+            close_authority: act_history.header.close_authority(),
+            pending_close_authority: act_history.header.pending_close_authority(),
+        });
+        Ok(())
+    }
+}
+
+/// Complete a two-step transfer of the close authority.
+#[derive(Accounts)]
+pub struct AcceptCloseAuthority<'info> {
+    /// The proposed close authority.
+    pending_close_authority: Signer<'info>,
+    /// CHECK: The history account being modified.
+    #[account(mut)]
+    account_state_history: UncheckedAccount<'info>,
+}
+
+impl<'info> AcceptCloseAuthority<'info> {
+    pub fn process(&mut self) -> Result<()> {
+        let mut data = self.account_state_history.data.borrow_mut();
+        let act_history = AccountHistoryRaw::from_buffer(&mut data)?;
+        if act_history.header.pending_close_authority == Pubkey::default()
+            || self.pending_close_authority.key() != act_history.header.pending_close_authority {
+            return err!(AccountHistoryProgramError::NotPendingCloseAuthority);
+        }
+        act_history.header.close_authority = self.pending_close_authority.key();
+        act_history.header.pending_close_authority = Pubkey::default();
+        emit!(CloseAuthorityChanged {
+            account_history: self.account_state_history.key(),
+            close_authority: Some(self.pending_close_authority.key()),
+            pending_close_authority: None,
+        });
+        Ok(())
+    }
+}
+
+/// Irrevocably give up the close authority, making the history account permanent.
+/// It can no longer be closed or resized, and its update authority can no longer change.
+#[derive(Accounts)]
+pub struct RenounceCloseAuthority<'info> {
+    /// The current close authority.
+    close_authority: Signer<'info>,
+    /// CHECK: The history account being modified.
+    #[account(mut)]
+    account_state_history: UncheckedAccount<'info>,
+}
+
+impl<'info> RenounceCloseAuthority<'info> {
+    pub fn process(&mut self) -> Result<()> {
+        let mut data = self.account_state_history.data.borrow_mut();
+        let act_history = AccountHistoryRaw::from_buffer(&mut data)?;
+        if self.close_authority.key() != act_history.header.close_authority {
+            return err!(AccountHistoryProgramError::NotCloseAuthority);
+        }
+        // A permanent account that can never be updated again is of no use to anyone.
+        if act_history.header.close_initiated.is_some() {
+            return err!(AccountHistoryProgramError::AccountBeingClosed);
+        }
+        act_history.header.close_authority = Pubkey::default();
+        act_history.header.pending_close_authority = Pubkey::default();
+        emit!(CloseAuthorityChanged {
+            account_history: self.account_state_history.key(),
+            close_authority: None,
+            pending_close_authority: None,
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+use crate::errors::AccountHistoryProgramError;
+use crate::events::UpdateAuthorityChanged;
+use crate::state::AccountHistoryRaw;
+
+/// Change the update authority of a history account,
+/// or make updates permissionless.
+#[derive(Accounts)]
+pub struct SetUpdateAuthority<'info> {
+    /// The close authority of the history account.
+    close_authority: Signer<'info>,
+    /// CHECK: The history account being modified.
+    #[account(mut)]
+    account_state_history: UncheckedAccount<'info>,
+}
+
+impl<'info> SetUpdateAuthority<'info> {
+    pub fn process(&mut self, new_update_authority: Option<Pubkey>) -> Result<()> {
+        let mut data = self.account_state_history.data.borrow_mut();
+        let act_history = AccountHistoryRaw::from_buffer(&mut data)?;
+        if self.close_authority.key() != act_history.header.close_authority {
+            return err!(AccountHistoryProgramError::NotCloseAuthority);
+        }
+        act_history.header.update_authority = new_update_authority.unwrap_or_default();
+        emit!(UpdateAuthorityChanged {
+            account_history: self.account_state_history.key(),
+            update_authority: act_history.header.update_authority(),
+        });
+        Ok(())
+    }
+}
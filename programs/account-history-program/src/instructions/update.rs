@@ -1,39 +1,259 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::clock::Slot;
+use anchor_spl::token::{Token, TokenAccount};
 use crate::errors::AccountHistoryProgramError;
-use crate::state::AccountHistoryRaw;
+use crate::events::{CloseInitiated, SnapshotPushed};
+use crate::instructions::reward_vault::pay_update_reward;
+use crate::state::{entry_slot, is_tombstone, AccountHistoryRaw, PushOutcome};
+use crate::state::reward_vault::RewardVault;
 
 
 /// Push a new blob of data onto a history account.
+/// When watching multiple accounts, the watched accounts after
+/// the first are passed as remaining accounts.
+///
+/// If the history account has a reward vault, passing it pays the signer its bounty
+/// for each update that records account data. Token bounties are paid to `recipient_tokens`.
 #[derive(Accounts)]
 pub struct Update<'info> {
-    /// Signer performing the update
+    /// Signer performing the update. Must be writable to receive a lamport bounty.
     signer: Signer<'info>,
     /// CHECK: The history account being updated
     #[account(mut)]
     account_state_history: UncheckedAccount<'info>,
     /// CHECK: The account's data type is not read by this program
     watched_account: UncheckedAccount<'info>,
+    /// The reward vault of the history account, to collect its bounty.
+    #[account(mut)]
+    reward_vault: Option<Account<'info, RewardVault>>,
+    /// The token account of a token reward vault.
+    #[account(mut)]
+    vault_tokens: Option<Account<'info, TokenAccount>>,
+    /// Receives the bounty of a token reward vault.
+    #[account(mut)]
+    recipient_tokens: Option<Account<'info, TokenAccount>>,
+    token_program: Option<Program<'info, Token>>,
 }
 
 impl<'info> Update<'info> {
-    pub fn process(&mut self) -> Result<()> {
-        let mut data = self.account_state_history.data.borrow_mut();
-        let mut account_history = AccountHistoryRaw::from_buffer(&mut data)?;
-        // Check we're indexing the correct account
-        if self.watched_account.key() != account_history.header.associated_account {
-            return err!(AccountHistoryProgramError::NotCorrectAccount);
+    pub fn process(&mut self, additional_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        let watched_accounts: Vec<AccountInfo> = [self.watched_account.to_account_info()]
+            .into_iter()
+            .chain(additional_accounts.iter().cloned())
+            .collect();
+        if matches!(&self.reward_vault, Some(reward_vault) if reward_vault.account_history != self.account_state_history.key()) {
+            return err!(AccountHistoryProgramError::InvalidRewardVault);
         }
-        // Check if the update authority matches (if update authority is not Default::default).
-        if account_history.header.update_authority != Pubkey::default()
-            && account_history.header.update_authority != self.signer.key() {
-            return err!(AccountHistoryProgramError::NotUpdateAuthority);
-        }
-        // Try to push a new data snapshot
-        let curr_slot = Clock::get()?.slot;
-        account_history.push(
-            &self.watched_account.data.borrow(),
-            curr_slot,
+        let previous_slot = {
+            let mut data = self.account_state_history.data.borrow_mut();
+            let act_history = AccountHistoryRaw::from_buffer(&mut data)?;
+            entry_slot(act_history.most_recent_entry())
+        };
+        let slot = Clock::get()?.slot;
+        let outcome = update_history(
+            &self.account_state_history,
+            &watched_accounts,
+            &self.signer.key(),
+            slot,
         )?;
+        if let Some(reward_vault) = &self.reward_vault {
+            pay_update_reward(
+                &self.account_state_history.to_account_info(),
+                &self.signer.to_account_info(),
+                reward_vault,
+                self.vault_tokens.as_ref(),
+                self.recipient_tokens.as_ref(),
+                self.token_program.as_ref(),
+                outcome,
+                previous_slot,
+                slot,
+            )?;
+        }
         Ok(())
     }
 }
+
+/// Push a new blob of data onto each of several history accounts watching a single account.
+/// The history accounts and their watched accounts are passed as remaining accounts,
+/// in alternating (history, watched account) pairs.
+///
+/// A history account that cannot be updated is skipped, without failing the others.
+/// Returns the result of each update, in order.
+#[derive(Accounts)]
+pub struct UpdateMany<'info> {
+    /// Signer performing the updates
+    signer: Signer<'info>,
+}
+
+/// The result of one of the updates of `update_many`.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateResult {
+    Updated(PushOutcome),
+    /// The update failed with the given error, encoded as by `u64::from(ProgramError)`.
+    /// Custom errors such as `NotNewSlot` keep their code, while built-in
+    /// errors are in the upper 32 bits. Decode it with `ProgramError::from`.
+    Skipped { error_code: u64 },
+}
+
+impl<'info> UpdateMany<'info> {
+    pub fn process(&self, pairs: &[AccountInfo<'info>]) -> Result<Vec<UpdateResult>> {
+        update_pairs(pairs, &self.signer.key(), Clock::get()?.slot)
+    }
+}
+
+/// Update each (history, watched account) pair, collecting the result of each update.
+pub(crate) fn update_pairs(pairs: &[AccountInfo], signer: &Pubkey, slot: Slot) -> Result<Vec<UpdateResult>> {
+    let chunks = pairs.chunks_exact(2);
+    if !chunks.remainder().is_empty() {
+        return err!(AccountHistoryProgramError::InvalidAccountPairs);
+    }
+    Ok(chunks
+        .map(|pair| {
+            // Writing to an account not owned by this program would fail the whole transaction
+            if pair[0].owner != &crate::ID || !pair[0].is_writable {
+                return Err(AccountHistoryProgramError::InvalidHistoryAccount.into());
+            }
+            update_history(&pair[0], &pair[1..], signer, slot)
+        })
+        .map(|result| match result {
+            Ok(outcome) => UpdateResult::Updated(outcome),
+            Err(e) => UpdateResult::Skipped { error_code: error_code(e) },
+        })
+        .collect())
+}
+
+/// The code of an error, as it would be reported if it failed the transaction.
+fn error_code(error: Error) -> u64 {
+    u64::from(ProgramError::from(error))
+}
+
+/// Check the watched accounts and update authority of a history account,
+/// push a new snapshot of the watched accounts at `slot`, and emit the matching event.
+pub(crate) fn update_history(
+    account_state_history: &AccountInfo,
+    watched_accounts: &[AccountInfo],
+    signer: &Pubkey,
+    slot: Slot,
+) -> Result<PushOutcome> {
+    let mut data = account_state_history.data.borrow_mut();
+    let mut account_history = AccountHistoryRaw::from_buffer(&mut data)?;
+    // Check we're indexing the correct accounts
+    let watched_accounts = &watched_accounts[..watched_accounts.len().min(account_history.watched_accounts().len())];
+    if watched_accounts.len() != account_history.watched_accounts().len()
+        || watched_accounts.iter().zip(account_history.watched_accounts())
+            .any(|(account, expected)| account.key() != *expected) {
+        return err!(AccountHistoryProgramError::NotCorrectAccount);
+    }
+    account_history.header.check_owners(
+        &watched_accounts.iter().map(|a| (*a.owner, a.data_len())).collect::<Vec<_>>(),
+    )?;
+    // Check if the update authority matches (if update authority is not Default::default).
+    if account_history.header.update_authority != Pubkey::default()
+        && account_history.header.update_authority != *signer {
+        return err!(AccountHistoryProgramError::NotUpdateAuthority);
+    }
+    // Try to push a new data snapshot
+    let watched_data: Vec<_> = watched_accounts.iter().map(|a| a.data.borrow()).collect();
+    let outcome = account_history.push_from_accounts(
+        &watched_data.iter().map(|d| &***d).collect::<Vec<&[u8]>>(),
+        slot,
+    )?;
+    emit_push(account_state_history.key(), &account_history, outcome);
+    Ok(outcome)
+}
+
+/// Emit the event matching the outcome of a push, if anything happened.
+pub(crate) fn emit_push(account_history: Pubkey, history: &AccountHistoryRaw, outcome: PushOutcome) {
+    match outcome {
+        PushOutcome::Pushed | PushOutcome::Tombstoned => {
+            let entry = history.most_recent_entry();
+            emit!(SnapshotPushed {
+                account_history,
+                slot: entry_slot(entry),
+                index: history.most_recent_index() as u32,
+                num_updates: history.num_updates() as u64,
+                tombstone: is_tombstone(entry),
+            });
+        },
+        PushOutcome::CloseInitiated => {
+            let slot = history.header.close_initiated.map_or(0, |slot| slot.get());
+            emit!(CloseInitiated {
+                account_history,
+                slot,
+                resolvable_slot: slot + history.header.min_close_delay as u64,
+            });
+        },
+        PushOutcome::Unchanged => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A history account buffer recording bytes 0..8 of `watched_account`.
+    fn mock_history(watched_account: Pubkey, update_authority: Pubkey) -> Vec<u8> {
+        let mut data = vec![0u8; AccountHistoryRaw::size_of(4, &[(0, 8)])];
+        let history = AccountHistoryRaw::init_from_buffer(&mut data, 1, &[], 16).unwrap();
+        history.regions[0].len = 8;
+        history.header.watched_accounts[0] = watched_account;
+        history.header.num_watched_accounts = 1;
+        history.header.update_authority = update_authority;
+        history.header.capacity = 4;
+        history.header.data_element_size = 16;
+        history.header.min_slot_delay = 1;
+        data
+    }
+
+    #[test]
+    fn update_many() {
+        let signer = Pubkey::new_unique();
+        let (history_keys, watched_keys) = ([(); 4].map(|_| Pubkey::new_unique()), [(); 4].map(|_| Pubkey::new_unique()));
+        let mut history_data = [
+            mock_history(watched_keys[0], Pubkey::default()),
+            mock_history(watched_keys[1], signer),
+            mock_history(watched_keys[2], Pubkey::new_unique()),
+            mock_history(watched_keys[3], Pubkey::default()),
+        ];
+        let mut watched_data = [(); 4].map(|_| 7u64.to_le_bytes().to_vec());
+        let mut lamports = [0u64; 8];
+        let owner = Pubkey::default();
+        let (history_lamports, watched_lamports) = lamports.split_at_mut(4);
+        let mut accounts = vec![];
+        for (i, ((history, watched), (history_lamports, watched_lamports))) in history_data
+            .iter_mut()
+            .zip(watched_data.iter_mut())
+            .zip(history_lamports.iter_mut().zip(watched_lamports.iter_mut()))
+            .enumerate() {
+            accounts.push(AccountInfo::new(&history_keys[i], false, true, history_lamports, history, &crate::ID, false, 0));
+            // The last pair names the wrong watched account
+            let watched_key = if i == 3 { &watched_keys[0] } else { &watched_keys[i] };
+            accounts.push(AccountInfo::new(watched_key, false, false, watched_lamports, watched, &owner, false, 0));
+        }
+
+        let code = |e: AccountHistoryProgramError| UpdateResult::Skipped { error_code: error_code(e.into()) };
+        assert_eq!(
+            update_pairs(&accounts, &signer, 10).unwrap(),
+            vec![
+                UpdateResult::Updated(PushOutcome::Pushed),
+                UpdateResult::Updated(PushOutcome::Pushed),
+                code(AccountHistoryProgramError::NotUpdateAuthority),
+                code(AccountHistoryProgramError::NotCorrectAccount),
+            ],
+        );
+        // Updating again in the same slot only fails the histories that were updated
+        assert_eq!(
+            update_pairs(&accounts[..4], &signer, 10).unwrap(),
+            vec![code(AccountHistoryProgramError::NotNewSlot); 2],
+        );
+        assert_eq!(error_code(AccountHistoryProgramError::NotNewSlot.into()), 6000 + AccountHistoryProgramError::NotNewSlot as u64);
+        // Built-in errors stay distinct from each other and from custom errors
+        let missing_signature = error_code(ProgramError::MissingRequiredSignature.into());
+        assert_ne!(missing_signature, error_code(ProgramError::InvalidAccountData.into()));
+        assert_eq!(ProgramError::from(missing_signature), ProgramError::MissingRequiredSignature);
+        assert_eq!(
+            update_pairs(&accounts[..3], &signer, 11).unwrap_err(),
+            AccountHistoryProgramError::InvalidAccountPairs.into(),
+        );
+    }
+}
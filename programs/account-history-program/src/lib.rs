@@ -1,11 +1,20 @@
+#[cfg(feature = "cpi")]
+pub mod cpi_helpers;
 pub mod errors;
+pub mod events;
 pub mod instructions;
+pub mod presets;
 pub mod state;
+pub mod stats;
 
 use anchor_lang::prelude::*;
 
 pub use errors::AccountHistoryProgramError;
 use instructions::*;
+use presets::Preset;
+use presets::pyth::{PythPriceSnapshot, PYTH_DATA_REGIONS};
+use state::schema::HistorySchema;
+use state::options::HistoryOptions;
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 
@@ -31,8 +40,106 @@ pub mod account_history_program {
         Ok(())
     }
 
-    pub fn update(ctx: Context<Update>) -> Result<()> {
-        ctx.accounts.process()?;
+    pub fn initialize_account_history_with_options(
+        ctx: Context<InitializeAccountHistoryWithOptions>,
+        capacity: u32,
+        data_regions: Vec<(u32, u32)>,
+        settings: HistorySettings,
+    ) -> Result<()> {
+        let data_regions = data_regions.into_iter().map(|(offset, len)| (0, offset, len)).collect();
+        ctx.accounts.process(settings.config(capacity, data_regions))?;
+        Ok(())
+    }
+
+    pub fn initialize_multi_account_history<'info>(
+        ctx: Context<'_, '_, '_, 'info, InitializeMultiAccountHistory<'info>>,
+        capacity: u32,
+        data_regions: Vec<(u8, u32, u32)>,
+        min_slot_delay: Option<u32>,
+        min_close_delay: Option<u32>,
+        update_authority: Option<Pubkey>,
+        options: Option<HistoryOptions>,
+    ) -> Result<()> {
+        let config = HistoryConfig {
+            capacity,
+            min_slot_delay: min_slot_delay.unwrap_or(1),
+            min_close_delay: min_close_delay.unwrap_or(0),
+            data_regions,
+            update_authority,
+            options: options.unwrap_or_default(),
+            preset: Preset::None,
+        };
+        ctx.accounts.process(config, ctx.remaining_accounts)?;
+        Ok(())
+    }
+
+    pub fn initialize_labeled_account_history<'info>(
+        ctx: Context<'_, '_, '_, 'info, InitializeLabeledAccountHistory<'info>>,
+        capacity: u32,
+        data_regions: Vec<(u8, u32, u32)>,
+        _label: String,
+        settings: HistorySettings,
+    ) -> Result<()> {
+        ctx.accounts.process(settings.config(capacity, data_regions), ctx.remaining_accounts)?;
+        Ok(())
+    }
+
+    pub fn initialize_pyth_price_history(
+        ctx: Context<InitializePythPriceHistory>,
+        capacity: u32,
+        max_price_age: u32,
+        min_slot_delay: Option<u32>,
+        min_close_delay: Option<u32>,
+        update_authority: Option<Pubkey>,
+        options: Option<HistoryOptions>,
+    ) -> Result<()> {
+        let config = HistoryConfig {
+            capacity,
+            min_slot_delay: min_slot_delay.unwrap_or(1),
+            min_close_delay: min_close_delay.unwrap_or(0),
+            data_regions: PYTH_DATA_REGIONS.to_vec(),
+            update_authority,
+            // The price account must stay owned by the Pyth program,
+            // and elements are always described by `PythPriceSnapshot`
+            options: HistoryOptions {
+                pin_owners: true,
+                schema: PythPriceSnapshot::schema(),
+                ..options.unwrap_or_default()
+            },
+            preset: Preset::Pyth { max_price_age },
+        };
+        ctx.accounts.process(config)?;
+        Ok(())
+    }
+
+    pub fn update<'info>(ctx: Context<'_, '_, '_, 'info, Update<'info>>) -> Result<()> {
+        ctx.accounts.process(ctx.remaining_accounts)?;
+        Ok(())
+    }
+
+    pub fn update_many<'info>(ctx: Context<'_, '_, '_, 'info, UpdateMany<'info>>) -> Result<Vec<UpdateResult>> {
+        ctx.accounts.process(ctx.remaining_accounts)
+    }
+
+    pub fn initialize_reward_vault(ctx: Context<InitializeRewardVault>, bounty: u64) -> Result<()> {
+        let bump = *ctx.bumps.get("reward_vault").unwrap();
+        ctx.accounts.process(bounty, bump)?;
+        Ok(())
+    }
+
+    pub fn initialize_token_reward_vault(ctx: Context<InitializeTokenRewardVault>, bounty: u64) -> Result<()> {
+        let bump = *ctx.bumps.get("reward_vault").unwrap();
+        ctx.accounts.process(bounty, bump)?;
+        Ok(())
+    }
+
+    pub fn deposit_rewards(ctx: Context<DepositRewards>, amount: u64) -> Result<()> {
+        ctx.accounts.process(amount)?;
+        Ok(())
+    }
+
+    pub fn withdraw_rewards(ctx: Context<WithdrawRewards>, amount: u64) -> Result<()> {
+        ctx.accounts.process(amount)?;
         Ok(())
     }
 
@@ -41,8 +148,49 @@ pub mod account_history_program {
         Ok(())
     }
 
+    pub fn cancel_account_close(ctx: Context<CancelAccountClose>) -> Result<()> {
+        ctx.accounts.process()?;
+        Ok(())
+    }
+
     pub fn resolve_account_close(ctx: Context<ResolveAccountClose>) -> Result<()> {
         ctx.accounts.process()?;
         Ok(())
     }
+
+    pub fn resize_account_history(ctx: Context<ResizeAccountHistory>, capacity: u32) -> Result<()> {
+        ctx.accounts.process(capacity)?;
+        Ok(())
+    }
+
+    pub fn set_close_authority(
+        ctx: Context<SetCloseAuthority>,
+        new_close_authority: Pubkey,
+        two_step: bool,
+    ) -> Result<()> {
+        ctx.accounts.process(new_close_authority, two_step)?;
+        Ok(())
+    }
+
+    pub fn accept_close_authority(ctx: Context<AcceptCloseAuthority>) -> Result<()> {
+        ctx.accounts.process()?;
+        Ok(())
+    }
+
+    pub fn renounce_close_authority(ctx: Context<RenounceCloseAuthority>) -> Result<()> {
+        ctx.accounts.process()?;
+        Ok(())
+    }
+
+    pub fn set_update_authority(
+        ctx: Context<SetUpdateAuthority>,
+        new_update_authority: Option<Pubkey>,
+    ) -> Result<()> {
+        ctx.accounts.process(new_update_authority)?;
+        Ok(())
+    }
+
+    pub fn query(ctx: Context<QueryAccountHistory>, query: HistoryQuery) -> Result<QueryResult> {
+        ctx.accounts.process(query)
+    }
 }
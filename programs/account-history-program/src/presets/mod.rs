@@ -0,0 +1,50 @@
+//! Ready-made configurations for recording well-known account types.
+pub mod pyth;
+pub mod token;
+
+use anchor_lang::prelude::*;
+
+/// A well-known account type recorded by a history account, whose data
+/// is validated on every update.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// Any account, recorded without validation.
+    #[default]
+    None,
+    /// A Pyth price account. Updates are rejected unless the aggregate price is
+    /// trading, and was published at most `max_price_age` slots ago.
+    Pyth { max_price_age: u32 },
+}
+
+impl Preset {
+    /// The value of the preset byte in the header.
+    pub(crate) fn tag(&self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Pyth { .. } => 1,
+        }
+    }
+
+    /// The value of the preset parameter in the header.
+    pub(crate) fn param(&self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Pyth { max_price_age } => *max_price_age,
+        }
+    }
+
+    pub(crate) fn from_header(tag: u8, param: u32) -> Self {
+        match tag {
+            1 => Self::Pyth { max_price_age: param },
+            _ => Self::None,
+        }
+    }
+
+    /// Ensure the data of the watched accounts is valid for this preset at `slot`.
+    pub fn validate(&self, data: &[&[u8]], slot: u64) -> Result<()> {
+        match self {
+            Self::None => Ok(()),
+            Self::Pyth { max_price_age } => pyth::validate(data[0], slot, *max_price_age),
+        }
+    }
+}
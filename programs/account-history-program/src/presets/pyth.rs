@@ -0,0 +1,148 @@
+//! Record the aggregate price of a Pyth price account.
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::clock::Slot;
+use bytemuck::{Pod, Zeroable};
+use pyth_sdk_solana::state::{load_price_account, PriceStatus};
+use crate::errors::AccountHistoryProgramError;
+use crate::state::schema::{FieldSchema, FieldType, HistorySchema};
+
+/// `FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH`, the Pyth oracle program on mainnet-beta.
+pub const PYTH_MAINNET_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+    220, 229, 235, 225, 228, 156, 59, 159, 17, 76, 181, 84, 76, 80, 169, 158,
+    192, 214, 146, 214, 63, 86, 121, 90, 224, 41, 172, 131, 217, 234, 139, 226,
+]);
+/// `gSbePebfvPy7tRqimPoVecS2UsBvYv46ynrzWocc92s`, the Pyth oracle program on devnet.
+pub const PYTH_DEVNET_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+    10, 26, 152, 51, 163, 118, 85, 43, 86, 183, 202, 13, 237, 25, 41, 23,
+    0, 87, 232, 39, 160, 198, 39, 244, 182, 71, 185, 238, 144, 153, 175, 180,
+]);
+/// `8tfDNiaEyrV6Q1U4DEXrEigs9DoDtkugzFbybENEbCDz`, the Pyth oracle program on testnet.
+pub const PYTH_TESTNET_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+    117, 61, 163, 65, 197, 233, 227, 200, 140, 19, 64, 169, 171, 17, 214, 13,
+    101, 41, 99, 1, 100, 174, 194, 7, 175, 86, 62, 175, 187, 219, 14, 253,
+]);
+
+/// Whether an account owner is one of the Pyth oracle programs.
+/// Price accounts owned by any other program could hold a fake feed.
+pub fn is_pyth_program(owner: &Pubkey) -> bool {
+    [PYTH_MAINNET_PROGRAM_ID, PYTH_DEVNET_PROGRAM_ID, PYTH_TESTNET_PROGRAM_ID].contains(owner)
+}
+
+/// Data regions of a Pyth price account recorded by the Pyth preset, in the order of the
+/// fields of `PythPriceSnapshot`: the aggregate price and confidence, the publish time,
+/// and the exponent followed by the number of component prices.
+pub const PYTH_DATA_REGIONS: [(u8, u32, u32); 3] = [(0, 208, 16), (0, 96, 8), (0, 20, 8)];
+
+/// An element of a history account created with the Pyth preset.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C)]
+pub struct PythPriceSnapshot {
+    pub slot: Slot,
+    pub price: i64,
+    pub conf: u64,
+    /// Unix timestamp at which the price was published.
+    pub publish_time: i64,
+    /// The price and confidence are in units of `10^expo`.
+    pub expo: i32,
+    pub num_components: u32,
+}
+
+impl HistorySchema for PythPriceSnapshot {
+    fn schema() -> Vec<FieldSchema> {
+        vec![
+            FieldSchema::new("price", 8, FieldType::I64),
+            FieldSchema::new("conf", 16, FieldType::U64),
+            FieldSchema::new("publish_time", 24, FieldType::I64),
+            FieldSchema::new("expo", 32, FieldType::I32),
+            FieldSchema::new("num_components", 36, FieldType::U32),
+        ]
+    }
+}
+
+/// Ensure a Pyth price account's aggregate price is trading,
+/// and was published at most `max_price_age` slots before `slot`.
+pub fn validate(data: &[u8], slot: Slot, max_price_age: u32) -> Result<()> {
+    let price_account = load_price_account(data)
+        .map_err(|_| AccountHistoryProgramError::InvalidPriceAccount)?;
+    if price_account.agg.status != PriceStatus::Trading {
+        return err!(AccountHistoryProgramError::PriceNotTrading);
+    }
+    if slot.saturating_sub(price_account.agg.pub_slot) > max_price_age as u64 {
+        return err!(AccountHistoryProgramError::StalePrice);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyth_sdk_solana::state::{AccountType, PriceAccount, MAGIC, VERSION_2};
+    use crate::state::regions::RegionLayout;
+
+    fn price_account() -> Box<PriceAccount> {
+        let mut account = Box::new(PriceAccount::zeroed());
+        account.magic = MAGIC;
+        account.ver = VERSION_2;
+        account.atype = AccountType::Price as u32;
+        account.expo = -8;
+        account.num = 5;
+        account.timestamp = 1_680_000_000;
+        account.agg.price = 2_800_000_000_000;
+        account.agg.conf = 1_500_000;
+        account.agg.status = PriceStatus::Trading;
+        account.agg.pub_slot = 100;
+        account
+    }
+
+    #[test]
+    fn layout() {
+        let account = price_account();
+        let data = bytemuck::bytes_of(&*account);
+        let layout = RegionLayout::new(&PYTH_DATA_REGIONS, 1).unwrap();
+        assert_eq!(layout.element_size() as usize, std::mem::size_of::<PythPriceSnapshot>());
+        let mut element = 7u64.to_le_bytes().to_vec();
+        for region in layout.regions() {
+            element.extend_from_slice(&data[region.offset as usize..][..region.len as usize]);
+        }
+        assert_eq!(
+            bytemuck::pod_read_unaligned::<PythPriceSnapshot>(&element),
+            PythPriceSnapshot {
+                slot: 7,
+                price: 2_800_000_000_000,
+                conf: 1_500_000,
+                publish_time: 1_680_000_000,
+                expo: -8,
+                num_components: 5,
+            },
+        );
+    }
+
+    #[test]
+    fn program_ids() {
+        assert_eq!(PYTH_MAINNET_PROGRAM_ID.to_string(), "FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH");
+        assert_eq!(PYTH_DEVNET_PROGRAM_ID.to_string(), "gSbePebfvPy7tRqimPoVecS2UsBvYv46ynrzWocc92s");
+        assert_eq!(PYTH_TESTNET_PROGRAM_ID.to_string(), "8tfDNiaEyrV6Q1U4DEXrEigs9DoDtkugzFbybENEbCDz");
+        assert!(is_pyth_program(&PYTH_MAINNET_PROGRAM_ID));
+        assert!(!is_pyth_program(&Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn validation() {
+        let mut account = price_account();
+        validate(bytemuck::bytes_of(&*account), 110, 10).unwrap();
+        assert_eq!(
+            validate(bytemuck::bytes_of(&*account), 111, 10).unwrap_err(),
+            AccountHistoryProgramError::StalePrice.into(),
+        );
+        account.agg.status = PriceStatus::Halted;
+        assert_eq!(
+            validate(bytemuck::bytes_of(&*account), 100, 10).unwrap_err(),
+            AccountHistoryProgramError::PriceNotTrading.into(),
+        );
+        account.magic = 0;
+        assert_eq!(
+            validate(bytemuck::bytes_of(&*account), 100, 10).unwrap_err(),
+            AccountHistoryProgramError::InvalidPriceAccount.into(),
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Record the balances, delegates and states of SPL Token and Token-2022 accounts,
+//! and the supply of their mints.
+//!
+//! Token-2022 accounts share the base layout of SPL Token accounts, followed by
+//! an account type byte and their extensions, so the same regions apply to both.
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::clock::Slot;
+use bytemuck::{Pod, Zeroable};
+use crate::state::schema::{FieldSchema, FieldType, HistorySchema};
+
+/// `TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA`
+pub const TOKEN_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+    6, 221, 246, 225, 215, 101, 161, 147, 217, 203, 225, 70, 206, 235, 121, 172,
+    28, 180, 133, 237, 95, 91, 55, 145, 58, 140, 245, 133, 126, 255, 0, 169,
+]);
+/// `TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb`
+pub const TOKEN_2022_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+    6, 221, 246, 225, 238, 117, 143, 222, 24, 66, 93, 188, 228, 108, 205, 218,
+    182, 26, 252, 77, 131, 185, 13, 39, 254, 189, 249, 40, 216, 161, 139, 252,
+]);
+
+/// Size of the base layout of a token account.
+pub const TOKEN_ACCOUNT_LEN: usize = 165;
+/// Size of the base layout of a mint.
+pub const MINT_LEN: usize = 82;
+
+/// The `amount` of a token account, recorded as a `HistoricalBalance`.
+pub const TOKEN_BALANCE_REGION: (u32, u32) = (64, 8);
+/// The `delegated_amount` of a token account, recorded as a `HistoricalDelegatedAmount`.
+pub const TOKEN_DELEGATED_AMOUNT_REGION: (u32, u32) = (121, 8);
+/// The `supply` of a mint, recorded as a `HistoricalSupply`.
+pub const MINT_SUPPLY_REGION: (u32, u32) = (36, 8);
+/// The `delegate` of a token account, a `COption<Pubkey>` recorded as a `HistoricalDelegate`.
+pub const TOKEN_DELEGATE_REGION: (u32, u32) = (72, 36);
+/// The `state` of a token account, recorded as a `HistoricalAccountState`.
+pub const TOKEN_STATE_REGION: (u32, u32) = (108, 1);
+
+/// The `state` of a token account that is frozen.
+pub const TOKEN_STATE_FROZEN: u8 = 2;
+
+/// An element of a history account recording `TOKEN_BALANCE_REGION`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C)]
+pub struct HistoricalBalance {
+    pub slot: Slot,
+    pub balance: u64,
+}
+
+/// An element of a history account recording `TOKEN_DELEGATED_AMOUNT_REGION`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C)]
+pub struct HistoricalDelegatedAmount {
+    pub slot: Slot,
+    pub delegated_amount: u64,
+}
+
+/// An element of a history account recording `MINT_SUPPLY_REGION`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C)]
+pub struct HistoricalSupply {
+    pub slot: Slot,
+    pub supply: u64,
+}
+
+/// An element of a history account recording `TOKEN_DELEGATE_REGION`.
+/// Packed, as the region is not a multiple of 8 bytes long.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct HistoricalDelegate {
+    pub slot: Slot,
+    /// 1 if the account has a delegate, 0 otherwise.
+    pub delegate_tag: u32,
+    pub delegate: Pubkey,
+}
+
+impl HistoricalDelegate {
+    pub fn delegate(&self) -> Option<Pubkey> {
+        (self.delegate_tag != 0).then_some(self.delegate)
+    }
+}
+
+/// An element of a history account recording `TOKEN_STATE_REGION`.
+/// Packed, as the region is not a multiple of 8 bytes long.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct HistoricalAccountState {
+    pub slot: Slot,
+    /// 0 if uninitialized, 1 if initialized, or `TOKEN_STATE_FROZEN`.
+    pub state: u8,
+}
+
+impl HistoricalAccountState {
+    pub fn is_frozen(&self) -> bool {
+        self.state == TOKEN_STATE_FROZEN
+    }
+}
+
+impl HistorySchema for HistoricalBalance {
+    fn schema() -> Vec<FieldSchema> {
+        vec![FieldSchema::new("balance", 8, FieldType::U64)]
+    }
+}
+
+impl HistorySchema for HistoricalDelegatedAmount {
+    fn schema() -> Vec<FieldSchema> {
+        vec![FieldSchema::new("delegated_amount", 8, FieldType::U64)]
+    }
+}
+
+impl HistorySchema for HistoricalSupply {
+    fn schema() -> Vec<FieldSchema> {
+        vec![FieldSchema::new("supply", 8, FieldType::U64)]
+    }
+}
+
+impl HistorySchema for HistoricalDelegate {
+    fn schema() -> Vec<FieldSchema> {
+        vec![
+            FieldSchema::new("delegate_tag", 8, FieldType::U32),
+            FieldSchema::new("delegate", 12, FieldType::Pubkey),
+        ]
+    }
+}
+
+impl HistorySchema for HistoricalAccountState {
+    fn schema() -> Vec<FieldSchema> {
+        vec![FieldSchema::new("state", 8, FieldType::U8)]
+    }
+}
+
+/// The kinds of accounts owned by the token programs that can be recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenAccountKind {
+    Account,
+    Mint,
+}
+
+/// Whether an account is a token account or a mint of SPL Token or Token-2022,
+/// judging by its owner and its data. `None` for any other account.
+pub fn token_account_kind(owner: &Pubkey, data: &[u8]) -> Option<TokenAccountKind> {
+    if *owner != TOKEN_PROGRAM_ID && *owner != TOKEN_2022_PROGRAM_ID {
+        return None;
+    }
+    match data.len() {
+        TOKEN_ACCOUNT_LEN => Some(TokenAccountKind::Account),
+        MINT_LEN => Some(TokenAccountKind::Mint),
+        // Token-2022 accounts with extensions store their type after the base account layout
+        len if len > TOKEN_ACCOUNT_LEN && *owner == TOKEN_2022_PROGRAM_ID => {
+            match data[TOKEN_ACCOUNT_LEN] {
+                1 => Some(TokenAccountKind::Mint),
+                2 => Some(TokenAccountKind::Account),
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind() {
+        assert_eq!(TOKEN_PROGRAM_ID.to_string(), "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+        assert_eq!(TOKEN_2022_PROGRAM_ID.to_string(), "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+        let mut data = vec![0u8; TOKEN_ACCOUNT_LEN];
+        data[64..72].copy_from_slice(&1000u64.to_le_bytes());
+        assert_eq!(token_account_kind(&TOKEN_PROGRAM_ID, &data), Some(TokenAccountKind::Account));
+        assert_eq!(token_account_kind(&TOKEN_2022_PROGRAM_ID, &data), Some(TokenAccountKind::Account));
+        assert_eq!(token_account_kind(&crate::ID, &data), None);
+        let (offset, len) = TOKEN_BALANCE_REGION;
+        let element = [0u64.to_le_bytes().as_slice(), &data[offset as usize..][..len as usize]].concat();
+        assert_eq!(bytemuck::pod_read_unaligned::<HistoricalBalance>(&element).balance, 1000);
+
+        assert_eq!(token_account_kind(&TOKEN_PROGRAM_ID, &[0; MINT_LEN]), Some(TokenAccountKind::Mint));
+
+        // Token-2022 accounts with extensions
+        let mut data = vec![0u8; TOKEN_ACCOUNT_LEN + 1 + 8];
+        data[TOKEN_ACCOUNT_LEN] = 1;
+        assert_eq!(token_account_kind(&TOKEN_2022_PROGRAM_ID, &data), Some(TokenAccountKind::Mint));
+        data[TOKEN_ACCOUNT_LEN] = 2;
+        assert_eq!(token_account_kind(&TOKEN_2022_PROGRAM_ID, &data), Some(TokenAccountKind::Account));
+        assert_eq!(token_account_kind(&TOKEN_PROGRAM_ID, &data), None);
+    }
+
+    #[test]
+    fn delegate_and_state() {
+        let delegate = Pubkey::new_unique();
+        let mut data = [0u8; TOKEN_ACCOUNT_LEN];
+        data[72..76].copy_from_slice(&1u32.to_le_bytes());
+        data[76..108].copy_from_slice(delegate.as_ref());
+        data[108] = TOKEN_STATE_FROZEN;
+        let element = |(offset, len): (u32, u32)| {
+            [10u64.to_le_bytes().as_slice(), &data[offset as usize..][..len as usize]].concat()
+        };
+
+        let element_size = 8 + TOKEN_DELEGATE_REGION.1 as usize;
+        assert_eq!(std::mem::size_of::<HistoricalDelegate>(), element_size);
+        let historical = bytemuck::pod_read_unaligned::<HistoricalDelegate>(&element(TOKEN_DELEGATE_REGION));
+        assert_eq!(historical.delegate(), Some(delegate));
+        let historical = HistoricalDelegate { delegate_tag: 0, ..historical };
+        assert_eq!(historical.delegate(), None);
+
+        let element_size = 8 + TOKEN_STATE_REGION.1 as usize;
+        assert_eq!(std::mem::size_of::<HistoricalAccountState>(), element_size);
+        let historical = bytemuck::pod_read_unaligned::<HistoricalAccountState>(&element(TOKEN_STATE_REGION));
+        assert!(historical.is_frozen());
+        assert_eq!({ historical.slot }, 10);
+
+        for (schema, size) in [
+            (HistoricalDelegate::schema(), std::mem::size_of::<HistoricalDelegate>()),
+            (HistoricalAccountState::schema(), std::mem::size_of::<HistoricalAccountState>()),
+        ] {
+            let end = schema.iter().map(|field| field.offset + field.field_type.size()).max().unwrap();
+            assert_eq!(end as usize, size);
+        }
+    }
+}
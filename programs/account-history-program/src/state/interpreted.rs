@@ -1,9 +1,11 @@
 use anchor_lang::prelude::*;
-use std::mem;
 use std::ops::Index;
 use anchor_lang::solana_program::pubkey::Pubkey;
 use bytemuck::Pod;
-use crate::state::{ACCOUNT_HISTORY_TAG, AccountHistoryHeader};
+use anchor_lang::solana_program::clock::Slot;
+use crate::state::{count_before_slot, entry_slot, oldest_index, split_buffer, AccountHistoryHeader};
+use crate::state::regions::Region;
+use crate::state::schema::{check_schema, FieldSchema, HistorySchema, SchemaField};
 use crate::errors::AccountHistoryProgramError;
 
 /// Data account, stores a data and a header.
@@ -12,6 +14,10 @@ use crate::errors::AccountHistoryProgramError;
 pub struct AccountHistory<'data, T: Pod> {
     /// Metadata about the account history
     pub(crate) header: &'data mut AccountHistoryHeader,
+    /// Locations of the account data being recorded
+    regions: &'data mut [Region],
+    /// Declared fields of each element
+    schema: &'data mut [SchemaField],
     /// Historical account state
     data: &'data mut [T],
 }
@@ -19,14 +25,28 @@ pub struct AccountHistory<'data, T: Pod> {
 impl<'data, T: Pod> AccountHistory<'data, T> {
     /// Constructor.
     pub fn from_buffer(data: &'data mut [u8]) -> Result<Self> {
-        let (header, data) = data.split_at_mut(mem::size_of::<AccountHistoryHeader>());
-        let header = bytemuck::from_bytes_mut::<AccountHistoryHeader>(header);
-        if header.account_tag != ACCOUNT_HISTORY_TAG {
-            return err!(AccountHistoryProgramError::InvalidAccountTag);
-        }
+        let (header, regions, schema, data) = split_buffer(data)?;
         let data = bytemuck::try_cast_slice_mut::<_, T>(data)
             .map_err(|_| AccountHistoryProgramError::InvalidDataType)?;
-        Ok(Self { header, data })
+        Ok(Self { header, regions, schema, data })
+    }
+
+    /// Same as `from_buffer`, but also ensures the declared schema describes `T`.
+    pub fn from_buffer_checked(data: &'data mut [u8]) -> Result<Self> where T: HistorySchema {
+        let history = Self::from_buffer(data)?;
+        history.check_schema()?;
+        Ok(history)
+    }
+
+    /// Ensure the declared schema describes `T`, failing with `MissingSchema`
+    /// if the history account was created without a schema.
+    pub fn check_schema(&self) -> Result<()> where T: HistorySchema {
+        check_schema::<T>(self.schema, self.header.data_element_size)
+    }
+
+    /// The declared fields of each element, empty if no schema was given.
+    pub fn schema(&self) -> Vec<FieldSchema> {
+        self.schema.iter().filter_map(SchemaField::to_schema).collect()
     }
 
     pub fn header(&self) -> AccountHistoryHeader {
@@ -54,7 +74,17 @@ impl<'data, T: Pod> AccountHistory<'data, T> {
 
     /// The account whose state is being recorded on this history account.
     pub fn associated_account(&self) -> Pubkey {
-        self.header.associated_account
+        self.header.watched_accounts[0]
+    }
+
+    /// All the accounts whose state is being recorded on this history account.
+    pub fn watched_accounts(&self) -> &[Pubkey] {
+        self.header.watched_accounts()
+    }
+
+    /// Locations of the account data recorded on each element, in the order they are stored.
+    pub fn data_regions(&self) -> &[Region] {
+        self.regions
     }
 
     /// Maximum number of elements supported by this account.
@@ -64,10 +94,37 @@ impl<'data, T: Pod> AccountHistory<'data, T> {
 
     /// The number of values indexed so far. Ranges from 0 to `self.header.capacity`.
     pub fn len(&self) -> usize {
-        std::cmp::min(
-            self.header.num_updates as usize,
-            self.header.capacity as usize,
-        )
+        self.header.len as usize
+    }
+
+    /// The `n`th oldest element, where zero is the oldest.
+    fn nth_oldest(&self, n: usize) -> &T {
+        &self[oldest_index(self.most_recent_index(), self.len(), self.capacity()) + n]
+    }
+
+    /// The slot of the `n`th oldest element, read from its first 8 bytes.
+    fn nth_oldest_slot(&self, n: usize) -> Slot {
+        entry_slot(bytemuck::bytes_of(self.nth_oldest(n)))
+    }
+
+    /// The most recent element recorded at or before `slot`. Runs in O(log n).
+    pub fn at_or_before_slot(&self, slot: Slot) -> Option<&T> {
+        let count = count_before_slot(self.len(), |n| self.nth_oldest_slot(n), slot, true);
+        count.checked_sub(1).map(|n| self.nth_oldest(n))
+    }
+
+    /// The oldest element recorded at or after `slot`. Runs in O(log n).
+    pub fn at_or_after_slot(&self, slot: Slot) -> Option<&T> {
+        let count = count_before_slot(self.len(), |n| self.nth_oldest_slot(n), slot, false);
+        (count < self.len()).then(|| self.nth_oldest(count))
+    }
+
+    /// The elements recorded from slot `start` to slot `end` inclusive,
+    /// from oldest to newest. Finding the range runs in O(log n).
+    pub fn range_between_slots(&self, start: Slot, end: Slot) -> impl Iterator<Item = &T> {
+        let from = count_before_slot(self.len(), |n| self.nth_oldest_slot(n), start, false);
+        let to = count_before_slot(self.len(), |n| self.nth_oldest_slot(n), end, true);
+        (from..to.max(from)).map(|n| self.nth_oldest(n))
     }
 }
 
@@ -144,7 +201,7 @@ impl<'data, T: Pod> Iterator for AccountHistoryIteratorRev<'data, T> {
 
 impl<'data, T: Pod> From<&'data AccountHistory<'data, T>> for AccountHistoryIteratorRev<'data, T> {
     fn from(value: &'data AccountHistory<'data, T>) -> Self {
-        let start = (value.most_recent_index() + 1) % value.capacity();
+        let start = oldest_index(value.most_recent_index(), value.len(), value.capacity());
         Self {
             inner: &value,
             counter: 0,
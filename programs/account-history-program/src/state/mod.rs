@@ -1,4 +1,8 @@
 pub mod interpreted;
+pub mod options;
+pub mod regions;
+pub mod reward_vault;
+pub mod schema;
 
 use crate::errors::AccountHistoryProgramError;
 use anchor_lang::prelude::*;
@@ -7,9 +11,80 @@ use bytemuck::{Pod, Zeroable};
 use std::mem;
 use std::num::NonZeroU64;
 use std::ops::Index;
+use options::{ShrinkPolicy, UnchangedPolicy};
+use crate::presets::Preset;
+use regions::{check_bounds, init_region_table, region_table_size, split_region_table, Region};
+use schema::{check_schema, init_schema_table, schema_table_size, split_schema_table, FieldSchema, HistorySchema, SchemaField};
 
-/// Equivalent to `SHA256(b"account:AccountHistory")[0..8]`
-pub const ACCOUNT_HISTORY_TAG: [u8; 8] = [242, 155, 38, 23, 9, 248, 25, 205];
+/// Equivalent to `SHA256(b"account:VersionedAccountHistory")[0..8]`
+pub const ACCOUNT_HISTORY_TAG: [u8; 8] = [216, 105, 27, 103, 56, 125, 50, 6];
+
+/// Equivalent to `SHA256(b"account:AccountHistory")[0..8]`. Tags accounts created before
+/// the header carried a layout version, whose byte at the offset of the version is part of
+/// a pubkey, so they are recognized by their tag and rejected as unsupported.
+pub const LEGACY_ACCOUNT_HISTORY_TAG: [u8; 8] = [242, 155, 38, 23, 9, 248, 25, 205];
+
+/// Current layout version of the header, region table and schema table.
+/// Version 1 accounts have no schema table, and are rejected as unsupported.
+pub const ACCOUNT_HISTORY_VERSION: u8 = 2;
+
+/// Set on the slot of elements recorded while a watched account was too small
+/// to hold its data regions, under `ShrinkPolicy::Tombstone`. The rest of such
+/// an element is zero-filled.
+pub const TOMBSTONE_FLAG: u64 = 1 << 63;
+
+/// The slot at which an element was recorded, without the `TOMBSTONE_FLAG`.
+pub fn entry_slot(entry: &[u8]) -> Slot {
+    bytemuck::pod_read_unaligned::<Slot>(&entry[..8]) & !TOMBSTONE_FLAG
+}
+
+/// Whether an element was recorded while a watched account was too small to hold
+/// its data regions, in which case the element's data is zero-filled.
+pub fn is_tombstone(entry: &[u8]) -> bool {
+    bytemuck::pod_read_unaligned::<Slot>(&entry[..8]) & TOMBSTONE_FLAG != 0
+}
+
+/// Position of the oldest element in a ring buffer, given the position of the most recent one.
+pub(crate) fn oldest_index(most_recent_index: usize, len: usize, capacity: usize) -> usize {
+    (most_recent_index + capacity + 1 - len) % capacity
+}
+
+/// Binary search for the number of elements, counting from the oldest, whose slot is
+/// before `slot`, or at or before `slot` when `inclusive`. Relies on slots increasing
+/// from oldest to newest. `slot_of` returns the slot of the `n`th oldest element.
+pub(crate) fn count_before_slot(len: usize, slot_of: impl Fn(usize) -> Slot, slot: Slot, inclusive: bool) -> usize {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let mid_slot = slot_of(mid);
+        if mid_slot < slot || (inclusive && mid_slot == slot) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+/// The result of successfully pushing onto a history account.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushOutcome {
+    /// A snapshot of the watched accounts was recorded.
+    Pushed,
+    /// A watched account was too small to hold its data regions,
+    /// so a tombstone element was recorded instead.
+    Tombstoned,
+    /// A watched account was too small to hold its data regions,
+    /// so the close procedure was started instead.
+    CloseInitiated,
+    /// The data regions were identical to the most recent element,
+    /// so nothing was recorded.
+    Unchanged,
+}
+
+/// Maximum number of accounts whose data can be recorded
+/// together in a single history account.
+pub const MAX_WATCHED_ACCOUNTS: usize = 4;
 
 
 /// PDA generation just takes a random 32-byte seed.
@@ -22,24 +97,68 @@ pub fn account_history_address(seed: [u8; 32]) -> (Pubkey, u8) {
     )
 }
 
+/// Maximum length in bytes of the label of a labeled history account.
+pub const MAX_LABEL_LEN: usize = 32;
+
+/// PDA of a labeled history account, derived from the first watched account,
+/// the account that created the history, and a label of at most `MAX_LABEL_LEN` bytes.
+pub fn labeled_account_history_address(watched_account: &Pubkey, creator: &Pubkey, label: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            watched_account.as_ref(),
+            creator.as_ref(),
+            label.as_bytes(),
+        ],
+        &crate::ID,
+    )
+}
+
 
 /// Contains metadata like the account's capacity, element size,
-/// number of updates, and locations of the account data being
-/// recorded.
+/// number of updates, and the accounts being recorded.
+///
+/// The header is followed by a length-prefixed table of the data regions
+/// being recorded (see [regions::region_table_size]), then by the table of fields
+/// describing each element, empty unless a schema was given (see [schema::schema_table_size]),
+/// and then by the elements.
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 #[repr(C)]
 pub struct AccountHistoryHeader {
     account_tag: [u8; 8],
-    /// The target account. Only historical data from this account will be indexed.
-    pub(crate) associated_account: Pubkey,
+    /// Layout version of the header, region table and schema table.
+    pub(crate) version: u8,
+    /// A `ShrinkPolicy`, applied when a watched account is too small to hold its data regions.
+    pub(crate) shrink_policy: u8,
+    /// An `UnchangedPolicy`, applied when the data regions are identical to the most recent element.
+    pub(crate) unchanged_policy: u8,
+    /// Identifies the `Preset` whose validation is applied on every update.
+    pub(crate) preset: u8,
+    /// Parameter of the preset, such as the maximum age of a Pyth price.
+    pub(crate) preset_param: u32,
+    /// The target accounts. Only historical data from these accounts will be indexed.
+    /// The first entry is the associated account, and unused entries are `Pubkey::default()`.
+    pub(crate) watched_accounts: [Pubkey; MAX_WATCHED_ACCOUNTS],
+    /// If not `Pubkey::default()`, the program that must own the watched account
+    /// at the same index for updates to succeed.
+    pub(crate) watched_account_owners: [Pubkey; MAX_WATCHED_ACCOUNTS],
     /// Only this account can close the history account and reclaim its rent lamports.
+    /// `Pubkey::default()` when the close authority has been renounced.
     pub(crate) close_authority: Pubkey,
+    /// If not `Pubkey::default()`, this account can accept a transfer of the close authority.
+    pub(crate) pending_close_authority: Pubkey,
     /// If not `Pubkey::default()`, only this address can sign for historical updates.
     pub(crate) update_authority: Pubkey,
     /// Total amount of space available for elements.
     pub(crate) capacity: u32,
+    /// The number of elements currently stored. Ranges from 0 to `capacity`.
+    pub(crate) len: u32,
     /// Includes the size of the slot.
     pub(crate) data_element_size: u32,
+    /// The number of entries in use in `watched_accounts`.
+    pub(crate) num_watched_accounts: u8,
+    /// Non-zero once a `RewardVault` is attached, which must be swept when closing.
+    pub(crate) has_reward_vault: u8,
+    _padding: [u8; 2],
     /// Total number of updates that have executed.
     num_updates: u64,
     /// New data must be at least this many slots new compared to the
@@ -50,71 +169,203 @@ pub struct AccountHistoryHeader {
     /// Zero when uninitialized. Slot number of when the history account
     /// close process was started.
     pub(crate) close_initiated: Option<NonZeroU64>,
-    /// Collection of byte offsets and ranges from which to collect
-    /// account state.
-    /// Paired values, in the form of (offset, range). For example,
-    /// (0,8) signifies the first 8 bytes. (48, 16) signifies
-    /// a 16 byte span starting at the 48th byte.
-    /// Maximum of 8 pairs of (offset, range), and they must
-    /// be non-zero in length
-    pub(crate) data_regions: [u32; 16],
 }
 
 impl Default for AccountHistoryHeader {
     fn default() -> Self {
         Self {
             account_tag: ACCOUNT_HISTORY_TAG,
-            associated_account: Default::default(),
+            version: ACCOUNT_HISTORY_VERSION,
+            shrink_policy: ShrinkPolicy::Error as u8,
+            unchanged_policy: UnchangedPolicy::Record as u8,
+            preset: 0,
+            preset_param: 0,
+            watched_accounts: Default::default(),
+            watched_account_owners: Default::default(),
             close_authority: Default::default(),
+            pending_close_authority: Default::default(),
             update_authority: Default::default(),
             capacity: 0,
+            len: 0,
             data_element_size: 0,
+            num_watched_accounts: 0,
+            has_reward_vault: 0,
+            _padding: [0; 2],
             num_updates: 0,
             min_slot_delay: 0,
             min_close_delay: 0,
             close_initiated: None,
-            data_regions: [0; 16],
         }
     }
 }
 
+impl AccountHistoryHeader {
+    /// `None` when the close authority has been renounced, meaning
+    /// the history account is permanent.
+    pub fn close_authority(&self) -> Option<Pubkey> {
+        (self.close_authority != Pubkey::default()).then_some(self.close_authority)
+    }
+
+    /// The account proposed as the next close authority, if any.
+    pub fn pending_close_authority(&self) -> Option<Pubkey> {
+        (self.pending_close_authority != Pubkey::default()).then_some(self.pending_close_authority)
+    }
+
+    /// `None` when updates are permissionless.
+    pub fn update_authority(&self) -> Option<Pubkey> {
+        (self.update_authority != Pubkey::default()).then_some(self.update_authority)
+    }
+
+    /// What happens when a watched account is too small to hold its data regions.
+    pub fn shrink_policy(&self) -> ShrinkPolicy {
+        ShrinkPolicy::from(self.shrink_policy)
+    }
+
+    /// What happens when the data regions are identical to the most recent element.
+    pub fn unchanged_policy(&self) -> UnchangedPolicy {
+        UnchangedPolicy::from(self.unchanged_policy)
+    }
+
+    /// Whether a `RewardVault` pays a bounty for updates of this history account.
+    pub fn has_reward_vault(&self) -> bool {
+        self.has_reward_vault != 0
+    }
+
+    /// Minimum number of slots between two recorded elements.
+    pub fn min_slot_delay(&self) -> u32 {
+        self.min_slot_delay
+    }
+
+    /// Minimum number of slots between initiating and resolving a close.
+    pub fn min_close_delay(&self) -> u32 {
+        self.min_close_delay
+    }
+
+    /// Slot at which the close procedure was started, if it is pending.
+    pub fn close_initiated(&self) -> Option<Slot> {
+        self.close_initiated.map(NonZeroU64::get)
+    }
+
+    /// The well-known account type being recorded, if any.
+    pub fn preset(&self) -> Preset {
+        Preset::from_header(self.preset, self.preset_param)
+    }
+
+    /// The accounts whose state is being recorded, in the order their
+    /// data regions are concatenated into each element.
+    pub fn watched_accounts(&self) -> &[Pubkey] {
+        &self.watched_accounts[..self.num_watched_accounts as usize]
+    }
+
+    /// The owner program pinned for each watched account,
+    /// or `None` where the owner is not pinned.
+    pub fn watched_account_owners(&self) -> Vec<Option<Pubkey>> {
+        self.watched_account_owners[..self.num_watched_accounts as usize]
+            .iter()
+            .map(|owner| (*owner != Pubkey::default()).then_some(*owner))
+            .collect()
+    }
+
+    /// Ensure each watched account is still owned by its pinned owner program,
+    /// given the owner and data length of each watched account in order.
+    ///
+    /// Accounts without data are exempt, since a closed account is handled
+    /// by the shrink policy rather than treated as having changed hands.
+    pub fn check_owners(&self, owners: &[(Pubkey, usize)]) -> Result<()> {
+        for (pinned, (owner, data_len)) in self.watched_account_owners.iter().zip(owners) {
+            if *pinned != Pubkey::default() && pinned != owner && *data_len != 0 {
+                return err!(AccountHistoryProgramError::WatchedAccountOwnerChanged);
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Data account, stores a data and a header.
 #[derive(Debug)]
 #[repr(C)]
 pub struct AccountHistoryRaw<'data> {
     /// Metadata about the account history
     pub(crate) header: &'data mut AccountHistoryHeader,
+    /// Locations of the account data being recorded
+    pub(crate) regions: &'data mut [Region],
+    /// Declared fields of each element
+    pub(crate) schema: &'data mut [SchemaField],
     /// Historical account state
     data: &'data mut [u8],
 }
 
+/// The parts of an account history buffer.
+pub(crate) type SplitBuffer<'a> = (&'a mut AccountHistoryHeader, &'a mut [Region], &'a mut [SchemaField], &'a mut [u8]);
+
+/// Split an account history buffer into its header, region table, schema table and element data,
+/// checking the account tag and layout version.
+pub(crate) fn split_buffer(data: &mut [u8]) -> Result<SplitBuffer<'_>> {
+    if data.len() < mem::size_of::<AccountHistoryHeader>() {
+        return err!(AccountHistoryProgramError::InvalidAccountTag);
+    }
+    let (header, data) = data.split_at_mut(mem::size_of::<AccountHistoryHeader>());
+    let header = bytemuck::from_bytes_mut::<AccountHistoryHeader>(header);
+    if header.account_tag == LEGACY_ACCOUNT_HISTORY_TAG {
+        return err!(AccountHistoryProgramError::UnsupportedVersion);
+    }
+    if header.account_tag != ACCOUNT_HISTORY_TAG {
+        return err!(AccountHistoryProgramError::InvalidAccountTag);
+    }
+    if header.version != ACCOUNT_HISTORY_VERSION {
+        return err!(AccountHistoryProgramError::UnsupportedVersion);
+    }
+    let (regions, data) = split_region_table(data)?;
+    let (schema, data) = split_schema_table(data)?;
+    Ok((header, regions, schema, data))
+}
+
 impl<'data> AccountHistoryRaw<'data> {
     /// Calculate the necessary size of an account history account
-    /// with the given parameters.
+    /// with the given parameters, without a schema.
     pub fn size_of(capacity: u32, data_locations: &[(u32, u32)]) -> usize {
         mem::size_of::<AccountHistoryHeader>()
+            + region_table_size(data_locations.len())
+            + schema_table_size(0)
             + (mem::size_of::<Slot>() + data_locations
                     .iter()
                     .map(|(_, len)| *len as usize)
                     .sum::<usize>()) * capacity as usize
     }
 
+    /// Calculate the necessary size of an account history account
+    /// watching multiple accounts, with the given parameters, without a schema.
+    pub fn size_of_multi(capacity: u32, data_locations: &[(u8, u32, u32)]) -> usize {
+        mem::size_of::<AccountHistoryHeader>()
+            + region_table_size(data_locations.len())
+            + schema_table_size(0)
+            + (mem::size_of::<Slot>() + data_locations
+                    .iter()
+                    .map(|(_, _, len)| *len as usize)
+                    .sum::<usize>()) * capacity as usize
+    }
+
     /// Constructor.
     pub fn from_buffer(data: &'data mut [u8]) -> Result<Self> {
-        let (header, data) = data.split_at_mut(mem::size_of::<AccountHistoryHeader>());
-        let header = bytemuck::from_bytes_mut::<AccountHistoryHeader>(header);
-        if header.account_tag != ACCOUNT_HISTORY_TAG {
-            return err!(AccountHistoryProgramError::InvalidAccountTag);
-        }
-        Ok(Self { header, data })
+        let (header, regions, schema, data) = split_buffer(data)?;
+        Ok(Self { header, regions, schema, data })
     }
 
-    // Used during initialization routine, sets account tag.
-    pub(crate) fn init_from_buffer(data: &'data mut [u8]) -> Result<Self> {
+    // Used during initialization routine, sets account tag, version,
+    // the size of the region table, and the schema of elements of `element_size` bytes.
+    pub(crate) fn init_from_buffer(
+        data: &'data mut [u8],
+        num_regions: usize,
+        schema: &[FieldSchema],
+        element_size: u32,
+    ) -> Result<Self> {
         let (header, data) = data.split_at_mut(mem::size_of::<AccountHistoryHeader>());
         let header = bytemuck::from_bytes_mut::<AccountHistoryHeader>(header);
         header.account_tag = ACCOUNT_HISTORY_TAG;
-        Ok(Self { header, data })
+        header.version = ACCOUNT_HISTORY_VERSION;
+        let (regions, data) = init_region_table(data, num_regions)?;
+        let (schema, data) = init_schema_table(data, schema, element_size)?;
+        Ok(Self { header, regions, schema, data })
     }
 
     pub fn header(&self) -> AccountHistoryHeader {
@@ -136,6 +387,15 @@ impl<'data> AccountHistoryRaw<'data> {
         &self.data[offset..offset + self.header.data_element_size as usize]
     }
 
+    /// The earliest slot at which `self.push` accepts a new element,
+    /// or `None` while the account is being closed.
+    pub fn next_update_slot(&self) -> Option<Slot> {
+        if self.header.close_initiated.is_some() {
+            return None;
+        }
+        Some(entry_slot(self.most_recent_entry()) + self.header.min_slot_delay as u64)
+    }
+
     /// The intended way to add a new element to this struct.
     /// Takes the current slot, and a reference to the account's data.
     ///
@@ -144,31 +404,126 @@ impl<'data> AccountHistoryRaw<'data> {
     ///
     /// You cannot add a value when this struct is being closed.
     /// This function also performs a minimum delay check on the passed slot number.
-    pub fn push(&mut self, data: &[u8], slot: Slot) -> Result<()> {
+    ///
+    /// If the account's data is too small to hold the data regions,
+    /// the outcome depends on `self.header.shrink_policy()`. If the data regions
+    /// are unchanged since the most recent element, it depends on `self.header.unchanged_policy()`.
+    pub fn push(&mut self, data: &[u8], slot: Slot) -> Result<PushOutcome> {
+        self.push_from_accounts(&[data], slot)
+    }
+
+    /// Same as `self.push`, but takes a reference to the data of every watched account,
+    /// in the same order as `self.header.watched_accounts()`.
+    /// All the accounts' data regions are recorded together in a single element.
+    pub fn push_from_accounts(&mut self, data: &[&[u8]], slot: Slot) -> Result<PushOutcome> {
+        if data.len() < self.header.num_watched_accounts as usize {
+            return err!(AccountHistoryProgramError::NotCorrectAccount);
+        }
         if self.header.close_initiated != None {
             return err!(AccountHistoryProgramError::AccountBeingClosed);
         }
-        let last_slot = bytemuck::from_bytes::<Slot>(&self.most_recent_entry()[..8]);
-        if *last_slot + self.header.min_slot_delay as u64 > slot {
+        let last_slot = entry_slot(self.most_recent_entry());
+        if last_slot + self.header.min_slot_delay as u64 > slot {
             return err!(AccountHistoryProgramError::NotNewSlot);
         }
-        // Obtain a mutable slice of the byte portion to be overwritten
+        let in_bounds = check_bounds(
+            self.regions,
+            &data.iter().map(|d| d.len()).collect::<Vec<_>>(),
+        );
+        if in_bounds.is_err() {
+            match self.header.shrink_policy() {
+                ShrinkPolicy::Error => return in_bounds.map(|_| PushOutcome::Pushed),
+                ShrinkPolicy::Close => {
+                    self.header.close_initiated = NonZeroU64::new(slot);
+                    return Ok(PushOutcome::CloseInitiated);
+                },
+                ShrinkPolicy::Tombstone => {},
+            }
+        }
+        if in_bounds.is_ok() {
+            self.header.preset().validate(data, slot)?;
+        }
+        // Concatenate the data regions, or zero-fill a tombstone
+        let mut new_data = vec![0u8; self.header.data_element_size as usize];
+        let outcome = if in_bounds.is_ok() {
+            new_data[..8].copy_from_slice(&slot.to_le_bytes());
+            let mut start = 8;
+            for region in self.regions.iter() {
+                let data = data[region.account_index as usize];
+                let offset = region.offset as usize;
+                new_data[start..start + region.len as usize]
+                    .copy_from_slice(&data[offset..offset + region.len as usize]);
+                start += region.len as usize;
+            }
+            PushOutcome::Pushed
+        } else {
+            new_data[..8].copy_from_slice(&(slot | TOMBSTONE_FLAG).to_le_bytes());
+            PushOutcome::Tombstoned
+        };
+        if outcome == PushOutcome::Pushed && self.len() > 0 {
+            let last = self.most_recent_entry();
+            if !is_tombstone(last) && last[8..] == new_data[8..] {
+                match self.header.unchanged_policy() {
+                    UnchangedPolicy::Record => {},
+                    UnchangedPolicy::Skip => return Ok(PushOutcome::Unchanged),
+                    UnchangedPolicy::Reject => return err!(AccountHistoryProgramError::DataUnchanged),
+                }
+            }
+        }
+        // Overwrite either an uninitialized or the oldest element
         let mut offset = (self.header.num_updates as usize + 1) % self.header.capacity as usize;
         offset *= self.header.data_element_size as usize;
-        let (_, from_offset) = self.data.split_at_mut(offset);
-        let (buf, _) = from_offset.split_at_mut(self.header.data_element_size as usize);
-        // Copy over the data
-        let mut new_data = slot.to_le_bytes().to_vec();
-        self.header
-            .data_regions
-            .chunks(2)
-            .for_each(|val| {
-                new_data.extend_from_slice(&data[val[0] as usize..(val[0] + val[1]) as usize]);
-            });
-        buf.copy_from_slice(&new_data);
+        self.data[offset..offset + self.header.data_element_size as usize].copy_from_slice(&new_data);
         // Increment the counter that keeps track of indexing
         self.header.num_updates += 1;
-        Ok(())
+        if self.header.len < self.header.capacity {
+            self.header.len += 1;
+        }
+        Ok(outcome)
+    }
+
+    /// Calculate the size of this account if it were resized to the given capacity.
+    pub fn size_with_capacity(&self, capacity: u32) -> usize {
+        mem::size_of::<AccountHistoryHeader>()
+            + region_table_size(self.regions.len())
+            + schema_table_size(self.schema.len())
+            + self.header.data_element_size as usize * capacity as usize
+    }
+
+    /// First half of a resize. Moves the (up to) `capacity` most recent elements
+    /// to the front of the buffer, ordered from oldest to newest, and zeroes
+    /// the remainder of the buffer.
+    ///
+    /// Must be followed by `self.end_resize` once the underlying buffer
+    /// has been reallocated to fit `capacity` elements.
+    pub(crate) fn begin_resize(&mut self, capacity: u32) {
+        let element_size = self.header.data_element_size as usize;
+        let old_capacity = self.capacity();
+        let len = self.len();
+        let kept = std::cmp::min(len, capacity as usize);
+        let oldest = (self.most_recent_index() + old_capacity + 1 - len) % old_capacity;
+        let buf = &mut self.data[..old_capacity * element_size];
+        // Bring the oldest element to the front, then drop the oldest elements
+        // that won't fit in the new capacity.
+        buf.rotate_left(oldest * element_size);
+        buf[..len * element_size].rotate_left((len - kept) * element_size);
+        buf[kept * element_size..].fill(0);
+        self.header.len = kept as u32;
+    }
+
+    /// Second half of a resize. Rotates the elements left at the front of the buffer
+    /// by `self.begin_resize` back into their ring buffer positions, so that the most
+    /// recent element is once again found at `self.most_recent_index()`.
+    pub(crate) fn end_resize(&mut self, capacity: u32) {
+        let element_size = self.header.data_element_size as usize;
+        let shift = (self.num_updates() + 1 - self.len()) % capacity as usize;
+        self.data[..capacity as usize * element_size].rotate_right(shift * element_size);
+        self.header.capacity = capacity;
+    }
+
+    /// Whether a `RewardVault` pays a bounty for updates of this account.
+    pub fn has_reward_vault(&self) -> bool {
+        self.header.has_reward_vault()
     }
 
     /// Total number of successful calls to `self.push`.
@@ -178,7 +533,27 @@ impl<'data> AccountHistoryRaw<'data> {
 
     /// The account whose state is being recorded on this history account.
     pub fn associated_account(&self) -> Pubkey {
-        self.header.associated_account
+        self.header.watched_accounts[0]
+    }
+
+    /// All the accounts whose state is being recorded on this history account.
+    pub fn watched_accounts(&self) -> &[Pubkey] {
+        self.header.watched_accounts()
+    }
+
+    /// The declared fields of each element, empty if no schema was given.
+    pub fn schema(&self) -> Vec<FieldSchema> {
+        self.schema.iter().filter_map(SchemaField::to_schema).collect()
+    }
+
+    /// Ensure the declared schema describes `T`.
+    pub fn check_schema<T: HistorySchema>(&self) -> Result<()> {
+        check_schema::<T>(self.schema, self.header.data_element_size)
+    }
+
+    /// Locations of the account data recorded on each element, in the order they are stored.
+    pub fn data_regions(&self) -> &[Region] {
+        self.regions
     }
 
     /// Maximum number of elements supported by this account.
@@ -188,10 +563,33 @@ impl<'data> AccountHistoryRaw<'data> {
 
     /// The number of values indexed so far. Ranges from 0 to `self.header.capacity`.
     pub fn len(&self) -> usize {
-        std::cmp::min(
-            self.header.num_updates as usize,
-            self.header.capacity as usize,
-        )
+        self.header.len as usize
+    }
+
+    /// The `n`th oldest element, where zero is the oldest.
+    fn nth_oldest(&self, n: usize) -> &[u8] {
+        &self[oldest_index(self.most_recent_index(), self.len(), self.capacity()) + n]
+    }
+
+    /// The most recent element recorded at or before `slot`. Runs in O(log n).
+    pub fn at_or_before_slot(&self, slot: Slot) -> Option<&[u8]> {
+        let count = count_before_slot(self.len(), |n| entry_slot(self.nth_oldest(n)), slot, true);
+        count.checked_sub(1).map(|n| self.nth_oldest(n))
+    }
+
+    /// The oldest element recorded at or after `slot`. Runs in O(log n).
+    pub fn at_or_after_slot(&self, slot: Slot) -> Option<&[u8]> {
+        let count = count_before_slot(self.len(), |n| entry_slot(self.nth_oldest(n)), slot, false);
+        (count < self.len()).then(|| self.nth_oldest(count))
+    }
+
+    /// The elements recorded from slot `start` to slot `end` inclusive,
+    /// from oldest to newest. Finding the range runs in O(log n).
+    pub fn range_between_slots(&self, start: Slot, end: Slot) -> impl Iterator<Item = &[u8]> {
+        let slot_of = |n| entry_slot(self.nth_oldest(n));
+        let from = count_before_slot(self.len(), slot_of, start, false);
+        let to = count_before_slot(self.len(), slot_of, end, true);
+        (from..to.max(from)).map(|n| self.nth_oldest(n))
     }
 }
 
@@ -205,8 +603,7 @@ pub struct AccountHistoryRawIterator<'data> {
 impl<'data> Index<usize> for AccountHistoryRaw<'data> {
     type Output = [u8];
     fn index(&self, index: usize) -> &Self::Output {
-        let index = index % self.header.capacity as usize;
-
+        let index = (index % self.header.capacity as usize) * self.header.data_element_size as usize;
         &self.data[index..index + self.header.data_element_size as usize]
     }
 }
@@ -220,7 +617,7 @@ impl<'data> Iterator for AccountHistoryRawIterator<'data> {
                 &self.val.data[self.index..self.index + self.val.header.data_element_size as usize];
             self.counter += 1;
             self.index = if self.index == 0 {
-                self.val.capacity() - self.val.header.data_element_size as usize
+                (self.val.capacity() - 1) * self.val.header.data_element_size as usize
             } else {
                 self.index - self.val.header.data_element_size as usize
             };
@@ -256,24 +653,73 @@ mod tests {
     const ELEM_SIZE: usize = 24usize;
     const CAPACITY: usize = 5usize;
 
+    /// Construct a mock history account buffer with the given header and data regions.
+    fn mock_account(header: AccountHistoryHeader, regions: &[Region]) -> Vec<u8> {
+        let mut table = vec![0u8; region_table_size(regions.len())];
+        table[..4].copy_from_slice(&(regions.len() as u32).to_le_bytes());
+        table[4..][..mem::size_of_val(regions)].copy_from_slice(bytemuck::cast_slice(regions));
+        [
+            bytemuck::bytes_of(&header),
+            &table,
+            &vec![0u8; schema_table_size(0)],
+            &vec![0u8; header.capacity as usize * header.data_element_size as usize],
+        ].concat()
+    }
+
+    fn region(account_index: u32, offset: u32, len: u32) -> Region {
+        Region { account_index, offset, len }
+    }
+
+    #[test]
+    fn account_tag() {
+        let header = AccountHistoryHeader {
+            num_watched_accounts: 1,
+            capacity: 2,
+            data_element_size: 16,
+            ..Default::default()
+        };
+        let mut data = mock_account(header, &[region(0, 0, 8)]);
+        AccountHistoryRaw::from_buffer(&mut data).unwrap();
+
+        // Accounts created before the header was versioned hold the associated account
+        // where the version now is, which may well start with the current version
+        data[..8].copy_from_slice(&LEGACY_ACCOUNT_HISTORY_TAG);
+        data[8] = ACCOUNT_HISTORY_VERSION;
+        assert_eq!(
+            AccountHistoryRaw::from_buffer(&mut data).unwrap_err(),
+            AccountHistoryProgramError::UnsupportedVersion.into(),
+        );
+        data[..8].copy_from_slice(&[0; 8]);
+        assert_eq!(
+            AccountHistoryRaw::from_buffer(&mut data).unwrap_err(),
+            AccountHistoryProgramError::InvalidAccountTag.into(),
+        );
+
+        // Version 1 accounts have no schema table, so their elements directly follow the regions
+        data[..8].copy_from_slice(&ACCOUNT_HISTORY_TAG);
+        data[8] = 1;
+        assert_eq!(
+            AccountHistoryRaw::from_buffer(&mut data).unwrap_err(),
+            AccountHistoryProgramError::UnsupportedVersion.into(),
+        );
+    }
+
     #[test]
     fn iteration() {
         let key = Pubkey::new_unique();
-        let mut header = AccountHistoryHeader {
+        let header = AccountHistoryHeader {
             account_tag: ACCOUNT_HISTORY_TAG,
-            associated_account: key,
+            watched_accounts: [key, Pubkey::default(), Pubkey::default(), Pubkey::default()],
+            num_watched_accounts: 1,
             capacity: CAPACITY as u32,
             data_element_size: ELEM_SIZE as u32,
             min_slot_delay: 1,
             ..Default::default()
         };
         // Pretend there are two target data regions: [0..8], [16..24]
-        header.data_regions[1] = 8;
-        header.data_regions[2] = 16;
-        header.data_regions[3] = 8;
+        let regions = [region(0, 0, 8), region(0, 16, 8)];
         // Construct a mock raw history account
-        let header_bytes = bytemuck::bytes_of(&header);
-        let mut mock_data = [header_bytes, &[0u8; CAPACITY * ELEM_SIZE]].concat();
+        let mut mock_data = mock_account(header, &regions);
         let mut vec = AccountHistoryRaw::from_buffer(&mut mock_data).unwrap();
         // Check that iterating an empty account does nothing
         let mut j = 0u64;
@@ -331,4 +777,255 @@ mod tests {
         assert_eq!(price.1, 15);
         assert_eq!(price.2, 30);
     }
+
+    #[test]
+    fn multiple_accounts() {
+        let header = AccountHistoryHeader {
+            capacity: CAPACITY as u32,
+            data_element_size: 24,
+            num_watched_accounts: 2,
+            min_slot_delay: 1,
+            ..Default::default()
+        };
+        // Record [8..16] of the second account, then [0..8] of the first account
+        let mut mock_data = mock_account(header, &[region(1, 8, 8), region(0, 0, 8)]);
+        let mut vec = AccountHistoryRaw::from_buffer(&mut mock_data).unwrap();
+        let first = [1u64.to_le_bytes(), 2u64.to_le_bytes()].concat();
+        let second = [3u64.to_le_bytes(), 4u64.to_le_bytes()].concat();
+        assert!(vec.push(&first, 1).is_err());
+        vec.push_from_accounts(&[&first, &second], 1).unwrap();
+        let entry = bytemuck::from_bytes::<[u64; 3]>(vec.most_recent_entry());
+        assert_eq!(entry, &[1, 4, 1]);
+    }
+
+    #[test]
+    fn resize() {
+        use crate::state::interpreted::{AccountHistoryIterator, AccountHistoryIteratorRev};
+        let header = AccountHistoryHeader {
+            capacity: CAPACITY as u32,
+            data_element_size: 16,
+            min_slot_delay: 1,
+            ..Default::default()
+        };
+        let mut mock_data = mock_account(header, &[region(0, 0, 8)]);
+        let resize = |buf: &mut Vec<u8>, capacity: u32| {
+            let new_size = {
+                let mut vec = AccountHistoryRaw::from_buffer(buf).unwrap();
+                vec.begin_resize(capacity);
+                vec.size_with_capacity(capacity)
+            };
+            buf.resize(new_size, 0);
+            AccountHistoryRaw::from_buffer(buf).unwrap().end_resize(capacity);
+        };
+        let newest_to_oldest = |buf: &mut Vec<u8>| {
+            let history = AccountHistory::<[u64; 2]>::from_buffer(buf).unwrap();
+            let values: Vec<u64> = AccountHistoryIterator::from(&history).map(|v| v[1]).collect();
+            let rev: Vec<u64> = AccountHistoryIteratorRev::from(&history).map(|v| v[1]).collect();
+            assert_eq!(values, rev.into_iter().rev().collect::<Vec<_>>());
+            values
+        };
+        // Push seven elements, wrapping around the buffer
+        let mut vec = AccountHistoryRaw::from_buffer(&mut mock_data).unwrap();
+        for i in 1..=7u64 {
+            vec.push(&(i * 10).to_le_bytes(), i).unwrap();
+        }
+        assert_eq!(newest_to_oldest(&mut mock_data), vec![70, 60, 50, 40, 30]);
+
+        // Shrinking keeps the most recent elements
+        resize(&mut mock_data, 3);
+        assert_eq!(newest_to_oldest(&mut mock_data), vec![70, 60, 50]);
+
+        // Growing keeps every element, and leaves room for more
+        resize(&mut mock_data, 6);
+        assert_eq!(newest_to_oldest(&mut mock_data), vec![70, 60, 50]);
+        let mut vec = AccountHistoryRaw::from_buffer(&mut mock_data).unwrap();
+        assert_eq!(vec.num_updates(), 7);
+        for i in 8..=11u64 {
+            vec.push(&(i * 10).to_le_bytes(), i).unwrap();
+        }
+        assert_eq!(newest_to_oldest(&mut mock_data), vec![110, 100, 90, 80, 70, 60]);
+
+        // Shrink to one element, then grow again
+        resize(&mut mock_data, 1);
+        assert_eq!(newest_to_oldest(&mut mock_data), vec![110]);
+        resize(&mut mock_data, 4);
+        let mut vec = AccountHistoryRaw::from_buffer(&mut mock_data).unwrap();
+        vec.push(&120u64.to_le_bytes(), 12).unwrap();
+        assert_eq!(newest_to_oldest(&mut mock_data), vec![120, 110]);
+    }
+
+    #[test]
+    fn slot_lookup() {
+        let header = AccountHistoryHeader {
+            capacity: CAPACITY as u32,
+            data_element_size: 16,
+            min_slot_delay: 1,
+            ..Default::default()
+        };
+        let mut mock_data = mock_account(header, &[region(0, 0, 8)]);
+        let mut vec = AccountHistoryRaw::from_buffer(&mut mock_data).unwrap();
+        // Push at slots 10 through 70, wrapping around so that slots 30 through 70 remain
+        for i in 1..=7u64 {
+            vec.push(&i.to_le_bytes(), i * 10).unwrap();
+        }
+        let value = |entry: &[u8]| *bytemuck::from_bytes::<u64>(&entry[8..]);
+        assert_eq!(AccountHistoryRawIterator::from(&vec).map(value).collect::<Vec<_>>(), vec![7, 6, 5, 4, 3]);
+        assert_eq!(value(&vec[1]), 6);
+
+        assert_eq!(vec.at_or_before_slot(29), None);
+        assert_eq!(vec.at_or_before_slot(30).map(value), Some(3));
+        assert_eq!(vec.at_or_before_slot(45).map(value), Some(4));
+        assert_eq!(vec.at_or_before_slot(u64::MAX).map(value), Some(7));
+        assert_eq!(vec.at_or_after_slot(0).map(value), Some(3));
+        assert_eq!(vec.at_or_after_slot(45).map(value), Some(5));
+        assert_eq!(vec.at_or_after_slot(70).map(value), Some(7));
+        assert_eq!(vec.at_or_after_slot(71), None);
+        assert_eq!(vec.range_between_slots(35, 60).map(value).collect::<Vec<_>>(), vec![4, 5, 6]);
+        assert_eq!(vec.range_between_slots(0, 100).map(value).collect::<Vec<_>>(), vec![3, 4, 5, 6, 7]);
+        assert_eq!(vec.range_between_slots(41, 49).count(), 0);
+        assert_eq!(vec.range_between_slots(60, 40).count(), 0);
+
+        let history = AccountHistory::<[u64; 2]>::from_buffer(&mut mock_data).unwrap();
+        assert_eq!(history.at_or_before_slot(29), None);
+        assert_eq!(history.at_or_before_slot(45), Some(&[40, 4]));
+        assert_eq!(history.at_or_after_slot(45), Some(&[50, 5]));
+        assert_eq!(history.at_or_after_slot(71), None);
+        assert_eq!(history.range_between_slots(35, 60).map(|v| v[1]).collect::<Vec<_>>(), vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn reader_layout() {
+        use account_history_reader::AccountHistoryRef;
+        assert_eq!(
+            mem::size_of::<AccountHistoryHeader>(),
+            mem::size_of::<account_history_reader::AccountHistoryHeader>(),
+        );
+        assert_eq!(mem::size_of::<SchemaField>(), account_history_reader::SCHEMA_FIELD_SIZE);
+        let key = Pubkey::new_unique();
+        let header = AccountHistoryHeader {
+            watched_accounts: [key, Pubkey::default(), Pubkey::default(), Pubkey::default()],
+            num_watched_accounts: 1,
+            capacity: CAPACITY as u32,
+            data_element_size: 16,
+            min_slot_delay: 1,
+            ..Default::default()
+        };
+        let mut mock_data = mock_account(header, &[region(0, 8, 8)]);
+        let mut vec = AccountHistoryRaw::from_buffer(&mut mock_data).unwrap();
+        for i in 1..=7u64 {
+            vec.push(&[[0u8; 8], (i * 10).to_le_bytes()].concat(), i).unwrap();
+        }
+        let history = AccountHistoryRef::<[u64; 2]>::from_buffer(&mock_data).unwrap();
+        assert_eq!(history.watched_accounts(), &[key]);
+        assert_eq!(history.len(), CAPACITY);
+        assert_eq!(history.num_updates(), 7);
+        assert_eq!(history.most_recent_entry(), &[7, 70]);
+        assert_eq!(history.iter().map(|v| v[1]).collect::<Vec<_>>(), vec![70, 60, 50, 40, 30]);
+        assert_eq!(history.data_regions()[0].offset, 8);
+    }
+
+    #[test]
+    fn unchanged_policy() {
+        let header = |unchanged_policy: UnchangedPolicy| AccountHistoryHeader {
+            capacity: CAPACITY as u32,
+            data_element_size: 16,
+            min_slot_delay: 1,
+            unchanged_policy: unchanged_policy as u8,
+            ..Default::default()
+        };
+        let value = 7u64.to_le_bytes();
+        let other = 8u64.to_le_bytes();
+
+        let mut mock_data = mock_account(header(UnchangedPolicy::Record), &[region(0, 0, 8)]);
+        let mut vec = AccountHistoryRaw::from_buffer(&mut mock_data).unwrap();
+        assert_eq!(vec.push(&value, 1).unwrap(), PushOutcome::Pushed);
+        assert_eq!(vec.push(&value, 2).unwrap(), PushOutcome::Pushed);
+        assert_eq!(vec.len(), 2);
+
+        let mut mock_data = mock_account(header(UnchangedPolicy::Skip), &[region(0, 0, 8)]);
+        let mut vec = AccountHistoryRaw::from_buffer(&mut mock_data).unwrap();
+        // The first element is always recorded, even if it is zeroed
+        assert_eq!(vec.push(&[0u8; 8], 1).unwrap(), PushOutcome::Pushed);
+        assert_eq!(vec.push(&value, 2).unwrap(), PushOutcome::Pushed);
+        assert_eq!(vec.push(&value, 3).unwrap(), PushOutcome::Unchanged);
+        assert_eq!(vec.len(), 2);
+        assert_eq!(entry_slot(vec.most_recent_entry()), 2);
+        assert_eq!(vec.push(&other, 4).unwrap(), PushOutcome::Pushed);
+        assert_eq!(vec.len(), 3);
+
+        let mut mock_data = mock_account(header(UnchangedPolicy::Reject), &[region(0, 0, 8)]);
+        let mut vec = AccountHistoryRaw::from_buffer(&mut mock_data).unwrap();
+        vec.push(&value, 1).unwrap();
+        assert_eq!(
+            vec.push(&value, 2).unwrap_err(),
+            AccountHistoryProgramError::DataUnchanged.into(),
+        );
+        assert_eq!(vec.push(&other, 2).unwrap(), PushOutcome::Pushed);
+    }
+
+    #[test]
+    fn owners() {
+        let token_program = Pubkey::new_unique();
+        let other_program = Pubkey::new_unique();
+        let header = AccountHistoryHeader {
+            num_watched_accounts: 2,
+            watched_account_owners: [token_program, Pubkey::default(), Pubkey::default(), Pubkey::default()],
+            ..Default::default()
+        };
+        assert_eq!(header.watched_account_owners(), vec![Some(token_program), None]);
+        header.check_owners(&[(token_program, 165), (other_program, 82)]).unwrap();
+        assert_eq!(
+            header.check_owners(&[(other_program, 165), (other_program, 82)]).unwrap_err(),
+            AccountHistoryProgramError::WatchedAccountOwnerChanged.into(),
+        );
+        // Closed accounts are left to the shrink policy
+        header.check_owners(&[(Pubkey::default(), 0), (other_program, 82)]).unwrap();
+    }
+
+    #[test]
+    fn shrink_policy() {
+        let header = |shrink_policy: ShrinkPolicy| AccountHistoryHeader {
+            capacity: CAPACITY as u32,
+            data_element_size: 16,
+            min_slot_delay: 1,
+            shrink_policy: shrink_policy as u8,
+            ..Default::default()
+        };
+        let full = 7u64.to_le_bytes();
+        let closed: &[u8] = &[];
+
+        let mut mock_data = mock_account(header(ShrinkPolicy::Error), &[region(0, 0, 8)]);
+        let mut vec = AccountHistoryRaw::from_buffer(&mut mock_data).unwrap();
+        assert_eq!(vec.push(&full, 1).unwrap(), PushOutcome::Pushed);
+        assert_eq!(
+            vec.push(closed, 2).unwrap_err(),
+            AccountHistoryProgramError::DataRegionOutOfBounds.into(),
+        );
+        assert_eq!(vec.num_updates(), 1);
+
+        let mut mock_data = mock_account(header(ShrinkPolicy::Tombstone), &[region(0, 0, 8)]);
+        let mut vec = AccountHistoryRaw::from_buffer(&mut mock_data).unwrap();
+        vec.push(&full, 1).unwrap();
+        assert_eq!(vec.push(closed, 2).unwrap(), PushOutcome::Tombstoned);
+        assert!(is_tombstone(vec.most_recent_entry()));
+        assert_eq!(entry_slot(vec.most_recent_entry()), 2);
+        assert_eq!(&vec.most_recent_entry()[8..], &[0u8; 8]);
+        // The tombstone flag does not interfere with the minimum slot delay
+        assert!(vec.push(&full, 2).is_err());
+        assert_eq!(vec.next_update_slot(), Some(3));
+        assert_eq!(vec.push(&full, 3).unwrap(), PushOutcome::Pushed);
+        assert!(!is_tombstone(vec.most_recent_entry()));
+
+        let mut mock_data = mock_account(header(ShrinkPolicy::Close), &[region(0, 0, 8)]);
+        let mut vec = AccountHistoryRaw::from_buffer(&mut mock_data).unwrap();
+        vec.push(&full, 1).unwrap();
+        assert_eq!(vec.push(closed, 2).unwrap(), PushOutcome::CloseInitiated);
+        assert_eq!(vec.header().close_initiated(), Some(2));
+        assert_eq!(vec.next_update_slot(), None);
+        assert_eq!(vec.num_updates(), 1);
+        assert_eq!(
+            vec.push(&full, 3).unwrap_err(),
+            AccountHistoryProgramError::AccountBeingClosed.into(),
+        );
+    }
 }
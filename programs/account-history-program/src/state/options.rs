@@ -0,0 +1,72 @@
+use anchor_lang::prelude::*;
+use crate::state::schema::{schema_fields_size, FieldSchema};
+
+/// Optional behaviors of a history account, chosen when it is initialized.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct HistoryOptions {
+    /// What to do when a watched account no longer holds all of its data regions.
+    pub shrink_policy: ShrinkPolicy,
+    /// Record the owner program of each watched account,
+    /// and refuse updates once an owner has changed.
+    pub pin_owners: bool,
+    /// What to do when the data regions are unchanged since the most recent element.
+    pub unchanged_policy: UnchangedPolicy,
+    /// Names and types of the fields of each element, stored on the history account
+    /// so that it can be decoded without knowing its type. Empty for no schema.
+    pub schema: Vec<FieldSchema>,
+}
+
+impl HistoryOptions {
+    /// Space taken by the schema fields, on top of `AccountHistoryRaw::size_of`.
+    pub fn schema_size(options: &Option<Self>) -> usize {
+        options.as_ref().map_or(0, |options| schema_fields_size(options.schema.len()))
+    }
+}
+
+/// What to do when a watched account is too small to hold its data regions,
+/// for example because it was closed.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ShrinkPolicy {
+    /// Reject the update with `DataRegionOutOfBounds`.
+    #[default]
+    Error = 0,
+    /// Record a zero-filled element, whose slot is flagged with `TOMBSTONE_FLAG`.
+    Tombstone = 1,
+    /// Record nothing, and start the close procedure of the history account.
+    Close = 2,
+}
+
+impl From<u8> for ShrinkPolicy {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Self::Tombstone,
+            2 => Self::Close,
+            _ => Self::Error,
+        }
+    }
+}
+
+/// What to do when the data regions being recorded are identical
+/// to those of the most recent element.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum UnchangedPolicy {
+    /// Record a new element anyway.
+    #[default]
+    Record = 0,
+    /// Record nothing, and succeed.
+    Skip = 1,
+    /// Reject the update with `DataUnchanged`.
+    Reject = 2,
+}
+
+impl From<u8> for UnchangedPolicy {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Self::Skip,
+            2 => Self::Reject,
+            _ => Self::Record,
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use anchor_lang::prelude::*;
+use bytemuck::{Pod, Zeroable};
+use std::mem;
+use crate::errors::AccountHistoryProgramError;
+
+/// Maximum number of data regions that can be recorded on a history account.
+pub const MAX_DATA_REGIONS: usize = 64;
+
+/// A contiguous span of bytes on one of the watched accounts.
+/// For example, an offset of 48 and a length of 16 signifies
+/// the 16 byte span starting at the 48th byte.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C)]
+pub struct Region {
+    /// Index of the watched account on which the region is found.
+    pub account_index: u32,
+    /// Byte offset where the region begins.
+    pub offset: u32,
+    /// Length of the region in bytes. Always non-zero.
+    pub len: u32,
+}
+
+/// Size of the region table stored after the header. The table is a `u32` count of
+/// regions followed by the regions themselves, padded to keep elements 8-byte aligned.
+pub fn region_table_size(num_regions: usize) -> usize {
+    let size = mem::size_of::<u32>() + num_regions * mem::size_of::<Region>();
+    (size + 7) & !7
+}
+
+/// Split the buffer that follows the header into the region table and the element data.
+pub(crate) fn split_region_table(data: &mut [u8]) -> Result<(&mut [Region], &mut [u8])> {
+    if data.len() < mem::size_of::<u32>() {
+        return err!(AccountHistoryProgramError::InvalidDataRegions);
+    }
+    let num_regions = *bytemuck::from_bytes::<u32>(&data[..mem::size_of::<u32>()]) as usize;
+    if num_regions > MAX_DATA_REGIONS || data.len() < region_table_size(num_regions) {
+        return err!(AccountHistoryProgramError::InvalidDataRegions);
+    }
+    let (table, data) = data.split_at_mut(region_table_size(num_regions));
+    let regions = &mut table[mem::size_of::<u32>()..][..num_regions * mem::size_of::<Region>()];
+    Ok((bytemuck::cast_slice_mut(regions), data))
+}
+
+/// Write an empty table with room for `num_regions` regions,
+/// and split it off from the element data.
+pub(crate) fn init_region_table(data: &mut [u8], num_regions: usize) -> Result<(&mut [Region], &mut [u8])> {
+    if num_regions > MAX_DATA_REGIONS {
+        return err!(AccountHistoryProgramError::TooManyDataRegions);
+    }
+    data[..mem::size_of::<u32>()].copy_from_slice(&(num_regions as u32).to_le_bytes());
+    split_region_table(data)
+}
+
+/// A validated set of data regions, in the order they are recorded on each element.
+///
+/// Every region is non-zero in length, refers to one of the watched accounts,
+/// ends within `u32` bounds, and does not overlap any other region on the same account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionLayout {
+    regions: Vec<Region>,
+}
+
+impl RegionLayout {
+    /// Validate tuples of (watched account index, offset, length).
+    pub fn new(regions: &[(u8, u32, u32)], num_watched_accounts: usize) -> Result<Self> {
+        if regions.is_empty() {
+            return err!(AccountHistoryProgramError::InvalidDataRegions);
+        }
+        if regions.len() > MAX_DATA_REGIONS {
+            return err!(AccountHistoryProgramError::TooManyDataRegions);
+        }
+        let regions = regions
+            .iter()
+            .map(|(account_index, offset, len)| {
+                if *len == 0
+                    || *account_index as usize >= num_watched_accounts
+                    || offset.checked_add(*len).is_none() {
+                    return err!(AccountHistoryProgramError::InvalidDataRegions);
+                }
+                Ok(Region {
+                    account_index: *account_index as u32,
+                    offset: *offset,
+                    len: *len,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // The total element size must also fit in a u32, slot included.
+        regions
+            .iter()
+            .try_fold(mem::size_of::<u64>() as u32, |size, region| size.checked_add(region.len))
+            .ok_or(AccountHistoryProgramError::InvalidDataRegions)?;
+        // Sorting by account and offset puts any overlapping regions next to each other.
+        let mut sorted = regions.clone();
+        sorted.sort_by_key(|region| (region.account_index, region.offset));
+        for pair in sorted.windows(2) {
+            if pair[0].account_index == pair[1].account_index
+                && pair[0].offset + pair[0].len > pair[1].offset {
+                return err!(AccountHistoryProgramError::OverlappingDataRegions);
+            }
+        }
+        Ok(Self { regions })
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Size of each element recorded with this layout, including the slot.
+    pub fn element_size(&self) -> u32 {
+        mem::size_of::<u64>() as u32 + self.regions.iter().map(|region| region.len).sum::<u32>()
+    }
+
+    /// Ensure every region lies within the data of its watched account,
+    /// given the data lengths of the watched accounts in order.
+    pub fn check_bounds(&self, data_lens: &[usize]) -> Result<()> {
+        check_bounds(&self.regions, data_lens)
+    }
+}
+
+/// Ensure every region lies within the data of its watched account,
+/// given the data lengths of the watched accounts in order.
+pub(crate) fn check_bounds(regions: &[Region], data_lens: &[usize]) -> Result<()> {
+    for region in regions {
+        let data_len = data_lens
+            .get(region.account_index as usize)
+            .ok_or(AccountHistoryProgramError::NotCorrectAccount)?;
+        if region.offset as usize + region.len as usize > *data_len {
+            return err!(AccountHistoryProgramError::DataRegionOutOfBounds);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_layout() {
+        let layout = RegionLayout::new(&[(0, 64, 8), (1, 0, 32), (0, 0, 32), (0, 32, 32)], 2).unwrap();
+        // Order is preserved
+        assert_eq!(layout.regions()[0], Region { account_index: 0, offset: 64, len: 8 });
+        assert_eq!(layout.regions()[1], Region { account_index: 1, offset: 0, len: 32 });
+        assert_eq!(layout.regions().len(), 4);
+        assert_eq!(layout.element_size(), 8 + 8 + 32 + 32 + 32);
+        // Same offsets on different accounts do not overlap
+        RegionLayout::new(&[(0, 0, 8), (1, 0, 8)], 2).unwrap();
+        // Regions at the very end of the u32 range are allowed
+        RegionLayout::new(&[(0, u32::MAX - 8, 8)], 1).unwrap();
+    }
+
+    #[test]
+    fn invalid_layouts() {
+        use AccountHistoryProgramError::*;
+        let error = |regions: &[(u8, u32, u32)], num_watched_accounts| {
+            RegionLayout::new(regions, num_watched_accounts).unwrap_err()
+        };
+        assert_eq!(error(&[], 1), InvalidDataRegions.into());
+        assert_eq!(error(&[(0, 0, 0)], 1), InvalidDataRegions.into());
+        assert_eq!(error(&[(1, 0, 8)], 1), InvalidDataRegions.into());
+        assert_eq!(error(&[(0, u32::MAX, 1)], 1), InvalidDataRegions.into());
+        assert_eq!(error(&[(0, 0, u32::MAX - 4)], 1), InvalidDataRegions.into());
+        let too_many: Vec<_> = (0..=MAX_DATA_REGIONS as u32).map(|i| (0, i * 8, 8)).collect();
+        assert_eq!(error(&too_many, 1), TooManyDataRegions.into());
+        assert_eq!(error(&[(0, 0, 8), (0, 0, 8)], 1), OverlappingDataRegions.into());
+        assert_eq!(error(&[(0, 16, 8), (0, 0, 17)], 1), OverlappingDataRegions.into());
+        assert_eq!(error(&[(0, 0, 64), (0, 8, 8)], 1), OverlappingDataRegions.into());
+    }
+
+    #[test]
+    fn bounds() {
+        let layout = RegionLayout::new(&[(0, 64, 8), (1, 0, 32)], 2).unwrap();
+        layout.check_bounds(&[72, 32]).unwrap();
+        assert!(layout.check_bounds(&[71, 32]).is_err());
+        assert!(layout.check_bounds(&[72, 0]).is_err());
+        assert!(layout.check_bounds(&[72]).is_err());
+    }
+}
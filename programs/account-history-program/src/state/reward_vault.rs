@@ -0,0 +1,40 @@
+//! A vault of lamports or SPL tokens paying a bounty to whoever updates a history account,
+//! so that permissionless histories do not rely on goodwill to stay up to date.
+use anchor_lang::prelude::*;
+
+pub const REWARD_VAULT_SEED: &[u8] = b"reward_vault";
+pub const REWARD_VAULT_TOKENS_SEED: &[u8] = b"reward_vault_tokens";
+
+/// Configuration of the bounty paid for each update of a history account.
+/// Lamport bounties are paid from the lamports of this account above its rent,
+/// and token bounties from the token account at `reward_vault_tokens_address`,
+/// whose authority is this account.
+#[account]
+#[derive(Debug, Default)]
+pub struct RewardVault {
+    pub account_history: Pubkey,
+    /// Mint of the token bounty, or `Pubkey::default()` for a lamport bounty.
+    pub mint: Pubkey,
+    /// Amount paid to the signer of each update that records an element.
+    pub bounty: u64,
+    pub bump: u8,
+}
+
+impl RewardVault {
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 1;
+
+    /// The mint of the token bounty, or `None` for a lamport bounty.
+    pub fn mint(&self) -> Option<Pubkey> {
+        (self.mint != Pubkey::default()).then_some(self.mint)
+    }
+}
+
+/// PDA of the reward vault of a history account.
+pub fn reward_vault_address(account_history: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REWARD_VAULT_SEED, account_history.as_ref()], &crate::ID)
+}
+
+/// PDA of the token account holding the rewards of a token reward vault.
+pub fn reward_vault_tokens_address(account_history: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REWARD_VAULT_TOKENS_SEED, account_history.as_ref()], &crate::ID)
+}
@@ -0,0 +1,262 @@
+//! An optional description of the fields of each element, written at initialization,
+//! so that consumers can interpret a history account without knowing its type out-of-band.
+use anchor_lang::prelude::*;
+use bytemuck::{Pod, Zeroable};
+use std::mem;
+use crate::errors::AccountHistoryProgramError;
+
+/// Maximum number of fields in a schema.
+pub const MAX_SCHEMA_FIELDS: usize = 64;
+
+/// Maximum length of a field name, in bytes.
+pub const MAX_FIELD_NAME_LEN: usize = 32;
+
+/// The type of a field. Numbers are little-endian.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Bool,
+    Pubkey,
+    /// Opaque bytes of the given length.
+    Bytes(u32),
+}
+
+impl FieldType {
+    /// Size of the field in bytes.
+    pub fn size(&self) -> u32 {
+        match self {
+            Self::U8 | Self::I8 | Self::Bool => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 => 4,
+            Self::U64 | Self::I64 => 8,
+            Self::U128 | Self::I128 => 16,
+            Self::Pubkey => 32,
+            Self::Bytes(len) => *len,
+        }
+    }
+
+    /// Whether the field is a number, which statistics and queries can read.
+    pub fn is_numeric(&self) -> bool {
+        !matches!(self, Self::Bool | Self::Pubkey | Self::Bytes(_))
+    }
+
+    fn tag(&self) -> u8 {
+        match self {
+            Self::U8 => 0,
+            Self::U16 => 1,
+            Self::U32 => 2,
+            Self::U64 => 3,
+            Self::U128 => 4,
+            Self::I8 => 5,
+            Self::I16 => 6,
+            Self::I32 => 7,
+            Self::I64 => 8,
+            Self::I128 => 9,
+            Self::Bool => 10,
+            Self::Pubkey => 11,
+            Self::Bytes(_) => 12,
+        }
+    }
+
+    fn from_tag(tag: u8, len: u32) -> Option<Self> {
+        Some(match tag {
+            0 => Self::U8,
+            1 => Self::U16,
+            2 => Self::U32,
+            3 => Self::U64,
+            4 => Self::U128,
+            5 => Self::I8,
+            6 => Self::I16,
+            7 => Self::I32,
+            8 => Self::I64,
+            9 => Self::I128,
+            10 => Self::Bool,
+            11 => Self::Pubkey,
+            12 => Self::Bytes(len),
+            _ => return None,
+        })
+    }
+}
+
+/// A named field of each element.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FieldSchema {
+    pub name: String,
+    /// Byte offset of the field within each element, counting the 8 bytes of the slot.
+    pub offset: u32,
+    pub field_type: FieldType,
+}
+
+impl FieldSchema {
+    pub fn new(name: &str, offset: u32, field_type: FieldType) -> Self {
+        Self { name: name.to_string(), offset, field_type }
+    }
+}
+
+/// A field as stored in the schema table.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C)]
+pub struct SchemaField {
+    /// UTF-8 name, zero-padded.
+    name: [u8; MAX_FIELD_NAME_LEN],
+    offset: u32,
+    len: u32,
+    field_type: u8,
+    _padding: [u8; 7],
+}
+
+impl SchemaField {
+    /// Validate a field of elements that are `element_size` bytes long.
+    pub fn new(field: &FieldSchema, element_size: u32) -> Result<Self> {
+        let len = field.field_type.size();
+        if field.name.is_empty()
+            || field.name.len() > MAX_FIELD_NAME_LEN
+            || len == 0
+            || (field.offset as usize) < mem::size_of::<u64>()
+            || !matches!(field.offset.checked_add(len), Some(end) if end <= element_size) {
+            return err!(AccountHistoryProgramError::InvalidSchema);
+        }
+        let mut name = [0; MAX_FIELD_NAME_LEN];
+        name[..field.name.len()].copy_from_slice(field.name.as_bytes());
+        Ok(Self {
+            name,
+            offset: field.offset,
+            len,
+            field_type: field.field_type.tag(),
+            _padding: [0; 7],
+        })
+    }
+
+    /// Decode the stored field, or `None` if it is malformed.
+    pub fn to_schema(&self) -> Option<FieldSchema> {
+        let name_len = self.name.iter().position(|b| *b == 0).unwrap_or(MAX_FIELD_NAME_LEN);
+        Some(FieldSchema {
+            name: std::str::from_utf8(&self.name[..name_len]).ok()?.to_string(),
+            offset: self.offset,
+            field_type: FieldType::from_tag(self.field_type, self.len)?,
+        })
+    }
+}
+
+/// Implemented by element types that declare their fields, so that they can be
+/// checked against the schema of a history account with `AccountHistory::check_schema`.
+pub trait HistorySchema: Pod {
+    /// The fields of the element, excluding the slot.
+    fn schema() -> Vec<FieldSchema>;
+}
+
+/// Size of the schema table stored after the region table. The table is a `u32` count
+/// of fields, padded to 8 bytes, followed by the fields themselves.
+pub fn schema_table_size(num_fields: usize) -> usize {
+    mem::size_of::<u64>() + schema_fields_size(num_fields)
+}
+
+/// Space taken by `num_fields` fields in the schema table.
+pub fn schema_fields_size(num_fields: usize) -> usize {
+    num_fields * mem::size_of::<SchemaField>()
+}
+
+/// Split the buffer that follows the region table into the schema table and the element data.
+pub(crate) fn split_schema_table(data: &mut [u8]) -> Result<(&mut [SchemaField], &mut [u8])> {
+    if data.len() < mem::size_of::<u64>() {
+        return err!(AccountHistoryProgramError::InvalidSchema);
+    }
+    let num_fields = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+    if num_fields > MAX_SCHEMA_FIELDS || data.len() < schema_table_size(num_fields) {
+        return err!(AccountHistoryProgramError::InvalidSchema);
+    }
+    let (table, data) = data.split_at_mut(schema_table_size(num_fields));
+    Ok((bytemuck::cast_slice_mut(&mut table[mem::size_of::<u64>()..]), data))
+}
+
+/// Validate and write the schema table, and split it off from the element data.
+pub(crate) fn init_schema_table<'a>(
+    data: &'a mut [u8],
+    schema: &[FieldSchema],
+    element_size: u32,
+) -> Result<(&'a mut [SchemaField], &'a mut [u8])> {
+    if schema.len() > MAX_SCHEMA_FIELDS {
+        return err!(AccountHistoryProgramError::InvalidSchema);
+    }
+    let fields = schema
+        .iter()
+        .map(|field| SchemaField::new(field, element_size))
+        .collect::<Result<Vec<_>>>()?;
+    data[..4].copy_from_slice(&(schema.len() as u32).to_le_bytes());
+    let (table, data) = split_schema_table(data)?;
+    table.copy_from_slice(&fields);
+    Ok((table, data))
+}
+
+/// Ensure the declared schema of a history account describes `T`.
+pub fn check_schema<T: HistorySchema>(schema: &[SchemaField], element_size: u32) -> Result<()> {
+    if element_size as usize != mem::size_of::<T>() {
+        return err!(AccountHistoryProgramError::InvalidDataType);
+    }
+    if schema.is_empty() {
+        return err!(AccountHistoryProgramError::MissingSchema);
+    }
+    let declared = schema.iter().map(SchemaField::to_schema).collect::<Option<Vec<_>>>();
+    if declared != Some(T::schema()) {
+        return err!(AccountHistoryProgramError::SchemaMismatch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, Clone, Copy, Pod, Zeroable)]
+    #[repr(C)]
+    struct Position {
+        slot: u64,
+        owner: Pubkey,
+        amount: u64,
+    }
+
+    impl HistorySchema for Position {
+        fn schema() -> Vec<FieldSchema> {
+            vec![
+                FieldSchema::new("owner", 8, FieldType::Pubkey),
+                FieldSchema::new("amount", 40, FieldType::U64),
+            ]
+        }
+    }
+
+    #[test]
+    fn schema() {
+        let mut data = vec![0u8; schema_table_size(2) + 48];
+        let (table, elements) = init_schema_table(&mut data, &Position::schema(), 48).unwrap();
+        assert_eq!(elements.len(), 48);
+        check_schema::<Position>(table, 48).unwrap();
+        assert_eq!(table[1].to_schema(), Some(FieldSchema::new("amount", 40, FieldType::U64)));
+
+        let (table, _) = split_schema_table(&mut data).unwrap();
+        assert_eq!(table.len(), 2);
+        table[1] = SchemaField::new(&FieldSchema::new("amount", 40, FieldType::I64), 48).unwrap();
+        assert_eq!(check_schema::<Position>(table, 48).unwrap_err(), AccountHistoryProgramError::SchemaMismatch.into());
+        assert_eq!(check_schema::<Position>(table, 40).unwrap_err(), AccountHistoryProgramError::InvalidDataType.into());
+        assert_eq!(check_schema::<Position>(&[], 48).unwrap_err(), AccountHistoryProgramError::MissingSchema.into());
+
+        // Fields must lie after the slot and within the element, and be named
+        for field in [
+            FieldSchema::new("slot", 0, FieldType::U64),
+            FieldSchema::new("amount", 41, FieldType::U64),
+            FieldSchema::new("", 8, FieldType::U64),
+            FieldSchema::new(&"a".repeat(MAX_FIELD_NAME_LEN + 1), 8, FieldType::U64),
+            FieldSchema::new("empty", 8, FieldType::Bytes(0)),
+        ] {
+            assert_eq!(SchemaField::new(&field, 48).unwrap_err(), AccountHistoryProgramError::InvalidSchema.into());
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Summary statistics over a window of a history account's elements,
+//! such as the time-weighted average of a recorded price.
+//!
+//! Values are read from each element by an accessor, and computed as `f64`,
+//! so that the same results are obtained on-chain and off-chain.
+//! Tombstone elements are ignored.
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::clock::Slot;
+use bytemuck::Pod;
+use crate::state::{entry_slot, is_tombstone, AccountHistoryRaw, AccountHistoryRawIterator};
+use crate::state::interpreted::{AccountHistory, AccountHistoryIterator};
+
+/// The elements to compute statistics over.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// The `n` most recent elements.
+    Last(u32),
+    /// The elements recorded from slot `start` to slot `end` inclusive.
+    Slots { start: Slot, end: Slot },
+}
+
+/// Statistics over the values of a window of elements.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    /// Number of elements in the window.
+    pub count: u64,
+    /// Slot of the oldest element in the window.
+    pub first_slot: Slot,
+    /// Slot of the most recent element in the window.
+    pub last_slot: Slot,
+    pub min: f64,
+    pub max: f64,
+    /// Simple mean, each element having the same weight.
+    pub mean: f64,
+    /// Time-weighted average, each value being weighted by the number of slots
+    /// until the next element. Equal to the mean when every element has the same slot.
+    pub twap: f64,
+    /// Population variance.
+    pub variance: f64,
+    /// Square root of the sum of squared log returns between consecutive elements.
+    /// `None` when there are fewer than two elements, or any value is not positive.
+    pub realized_volatility: Option<f64>,
+}
+
+impl Stats {
+    /// Population standard deviation.
+    pub fn std_dev(&self) -> f64 {
+        self.variance.sqrt()
+    }
+}
+
+/// The elements of a window, from oldest to newest, excluding tombstones.
+pub fn window<'a, T: Pod>(history: &'a AccountHistory<'a, T>, window: Window) -> Vec<&'a T> {
+    let entries: Vec<&T> = match window {
+        Window::Last(n) => {
+            let mut entries: Vec<&T> = AccountHistoryIterator::from(history).take(n as usize).collect();
+            entries.reverse();
+            entries
+        },
+        Window::Slots { start, end } => history.range_between_slots(start, end).collect(),
+    };
+    entries
+        .into_iter()
+        .filter(|entry| !is_tombstone(bytemuck::bytes_of(*entry)))
+        .collect()
+}
+
+/// Compute statistics over a window of a history account, reading each value with `value`.
+/// Returns `None` if the window is empty.
+pub fn compute<T: Pod>(
+    history: &AccountHistory<'_, T>,
+    window: Window,
+    value: impl Fn(&T) -> f64,
+) -> Option<Stats> {
+    compute_entries(&self::window(history, window), value)
+}
+
+/// Compute statistics over elements ordered from oldest to newest, reading each value with `value`.
+/// Returns `None` if there are no elements.
+pub fn compute_entries<T: Pod>(entries: &[&T], value: impl Fn(&T) -> f64) -> Option<Stats> {
+    let values: Vec<(Slot, f64)> = entries
+        .iter()
+        .map(|entry| (entry_slot(bytemuck::bytes_of(*entry)), value(entry)))
+        .collect();
+    compute_values(&values)
+}
+
+/// Same as `compute`, but over the raw bytes of each element of a history account.
+pub fn compute_raw(
+    history: &AccountHistoryRaw<'_>,
+    window: Window,
+    value: impl Fn(&[u8]) -> f64,
+) -> Option<Stats> {
+    let mut entries: Vec<&[u8]> = match window {
+        Window::Last(n) => {
+            let mut entries: Vec<&[u8]> = AccountHistoryRawIterator::from(history).take(n as usize).collect();
+            entries.reverse();
+            entries
+        },
+        Window::Slots { start, end } => history.range_between_slots(start, end).collect(),
+    };
+    entries.retain(|entry| !is_tombstone(entry));
+    let values: Vec<(Slot, f64)> = entries
+        .into_iter()
+        .map(|entry| (entry_slot(entry), value(entry)))
+        .collect();
+    compute_values(&values)
+}
+
+/// Compute statistics over (slot, value) pairs ordered from oldest to newest.
+/// Returns `None` if there are no values.
+pub fn compute_values(values: &[(Slot, f64)]) -> Option<Stats> {
+    let (first_slot, _) = *values.first()?;
+    let (last_slot, _) = *values.last()?;
+    let mut stats = Stats {
+        count: values.len() as u64,
+        first_slot,
+        last_slot,
+        min: f64::INFINITY,
+        max: f64::NEG_INFINITY,
+        mean: 0.0,
+        twap: 0.0,
+        variance: 0.0,
+        realized_volatility: None,
+    };
+    // Welford's algorithm for the mean and variance
+    let mut sum_squared_deviations = 0.0;
+    let mut weighted_sum = 0.0;
+    let mut sum_squared_returns = 0.0;
+    let mut positive = true;
+    let mut previous: Option<(Slot, f64)> = None;
+    for (i, (slot, v)) in values.iter().copied().enumerate() {
+        stats.min = stats.min.min(v);
+        stats.max = stats.max.max(v);
+        let delta = v - stats.mean;
+        stats.mean += delta / (i + 1) as f64;
+        sum_squared_deviations += delta * (v - stats.mean);
+        positive &= v > 0.0;
+        if let Some((previous_slot, previous_value)) = previous {
+            weighted_sum += previous_value * (slot - previous_slot) as f64;
+            if positive {
+                sum_squared_returns += (v / previous_value).ln().powi(2);
+            }
+        }
+        previous = Some((slot, v));
+    }
+    stats.variance = sum_squared_deviations / values.len() as f64;
+    stats.twap = if last_slot > first_slot {
+        weighted_sum / (last_slot - first_slot) as f64
+    } else {
+        stats.mean
+    };
+    if values.len() > 1 && positive {
+        stats.realized_volatility = Some(sum_squared_returns.sqrt());
+    }
+    Some(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A history of `[slot, price]` elements, pushed at the given slots.
+    fn mock_history(capacity: u32, prices: &[(Slot, u64)]) -> Vec<u8> {
+        let mut data = vec![0u8; AccountHistoryRaw::size_of(capacity, &[(0, 8)])];
+        let mut history = AccountHistoryRaw::init_from_buffer(&mut data, 1, &[], 16).unwrap();
+        history.regions[0].len = 8;
+        history.header.capacity = capacity;
+        history.header.data_element_size = 16;
+        for (slot, price) in prices {
+            history.push(&price.to_le_bytes(), *slot).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn stats() {
+        let mut data = mock_history(8, &[(10, 100), (12, 110), (18, 90), (20, 100)]);
+        let history = AccountHistory::<[u64; 2]>::from_buffer(&mut data).unwrap();
+        let price = |entry: &[u64; 2]| entry[1] as f64;
+
+        let stats = compute(&history, Window::Last(10), price).unwrap();
+        assert_eq!(stats.count, 4);
+        assert_eq!((stats.first_slot, stats.last_slot), (10, 20));
+        assert_eq!((stats.min, stats.max), (90.0, 110.0));
+        assert_eq!(stats.mean, 100.0);
+        // 100 for 2 slots, 110 for 6 slots, 90 for 2 slots
+        assert_eq!(stats.twap, (100.0 * 2.0 + 110.0 * 6.0 + 90.0 * 2.0) / 10.0);
+        assert_eq!(stats.variance, 50.0);
+        let returns = [110f64 / 100.0, 90.0 / 110.0, 100.0 / 90.0];
+        let volatility = returns.iter().map(|r| r.ln().powi(2)).sum::<f64>().sqrt();
+        assert!((stats.realized_volatility.unwrap() - volatility).abs() < 1e-12);
+
+        let stats = compute(&history, Window::Last(2), price).unwrap();
+        assert_eq!((stats.first_slot, stats.last_slot), (18, 20));
+        assert_eq!(stats.twap, 90.0);
+        assert_eq!(stats.mean, 95.0);
+
+        let stats = compute(&history, Window::Slots { start: 11, end: 18 }, price).unwrap();
+        assert_eq!(stats.count, 2);
+        assert_eq!((stats.min, stats.max), (90.0, 110.0));
+
+        // A single element has no returns, and its value as the average
+        let stats = compute(&history, Window::Last(1), price).unwrap();
+        assert_eq!(stats.twap, 100.0);
+        assert_eq!(stats.variance, 0.0);
+        assert_eq!(stats.realized_volatility, None);
+
+        assert_eq!(compute(&history, Window::Slots { start: 13, end: 17 }, price), None);
+        assert_eq!(compute(&history, Window::Last(0), price), None);
+    }
+
+    #[test]
+    fn non_positive_values() {
+        let mut data = mock_history(4, &[(1, 0), (2, 10)]);
+        let history = AccountHistory::<[u64; 2]>::from_buffer(&mut data).unwrap();
+        let stats = compute(&history, Window::Last(2), |entry| entry[1] as f64).unwrap();
+        assert_eq!(stats.mean, 5.0);
+        assert_eq!(stats.realized_volatility, None);
+    }
+}
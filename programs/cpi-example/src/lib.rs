@@ -0,0 +1,76 @@
+//! An example of coupling a program's account mutations with a CPI to the
+//! Account History Program, so that the last N states of a counter are always
+//! visible on-chain.
+//!
+//! The counter's history account is created off-chain, watching the counter's `count`,
+//! with this program's `HISTORY_AUTHORITY_SEED` PDA as its update authority.
+//! Only `increment` can then push snapshots onto it.
+use anchor_lang::prelude::*;
+use account_history_program::program::AccountHistoryProgram;
+
+declare_id!("Hprmqh5DdTaS7xnA42kKRY7Fm42VP6o426g7UeUZamks");
+
+/// Seed of the PDA that signs for updates to counter history accounts.
+pub const HISTORY_AUTHORITY_SEED: &[u8] = b"history-authority";
+
+/// Byte offset of `Counter::count`, after the Anchor discriminator and the authority.
+pub const COUNT_OFFSET: u32 = 8 + 32;
+
+/// The PDA that signs for updates to counter history accounts.
+pub fn history_authority_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[HISTORY_AUTHORITY_SEED], &crate::ID)
+}
+
+#[program]
+pub mod cpi_example {
+    use super::*;
+
+    pub fn initialize_counter(ctx: Context<InitializeCounter>) -> Result<()> {
+        ctx.accounts.counter.authority = ctx.accounts.authority.key();
+        ctx.accounts.counter.count = 0;
+        Ok(())
+    }
+
+    pub fn increment(ctx: Context<Increment>) -> Result<()> {
+        ctx.accounts.counter.count += 1;
+        // Write the counter back to its account, so the snapshot sees the new count.
+        ctx.accounts.counter.exit(&crate::ID)?;
+        account_history_program::cpi_helpers::update(
+            ctx.accounts.account_history_program.to_account_info(),
+            ctx.accounts.history_authority.to_account_info(),
+            ctx.accounts.account_state_history.to_account_info(),
+            &[ctx.accounts.counter.to_account_info()],
+            &[&[HISTORY_AUTHORITY_SEED, &[*ctx.bumps.get("history_authority").unwrap()]]],
+        )?;
+        Ok(())
+    }
+}
+
+#[account]
+pub struct Counter {
+    pub authority: Pubkey,
+    pub count: u64,
+}
+
+#[derive(Accounts)]
+pub struct InitializeCounter<'info> {
+    #[account(mut)]
+    authority: Signer<'info>,
+    #[account(init, payer=authority, space=8 + 32 + 8)]
+    counter: Account<'info, Counter>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Increment<'info> {
+    authority: Signer<'info>,
+    #[account(mut, has_one=authority)]
+    counter: Account<'info, Counter>,
+    /// CHECK: Only signs for the history account update.
+    #[account(seeds=[HISTORY_AUTHORITY_SEED], bump)]
+    history_authority: UncheckedAccount<'info>,
+    /// CHECK: Checked by the Account History Program.
+    #[account(mut)]
+    account_state_history: UncheckedAccount<'info>,
+    account_history_program: Program<'info, AccountHistoryProgram>,
+}
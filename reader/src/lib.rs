@@ -0,0 +1,417 @@
+//! Read-only, zero-copy access to Account History Program accounts.
+//!
+//! This crate does not depend on Anchor or on the program crate, so any on-chain
+//! program can read history accounts cheaply, without a mutable borrow:
+//!
+//! ```ignore
+//! let data = history_account.try_borrow_data()?;
+//! let history = AccountHistoryRef::<HistoricalBalance>::from_account_data(history_account.owner, &data)?;
+//! let latest = history.most_recent_entry();
+//! ```
+use bytemuck::{Pod, Zeroable};
+use solana_program::clock::Slot;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use std::mem;
+use std::num::NonZeroU64;
+use std::ops::Index;
+use thiserror::Error;
+
+solana_program::declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
+
+/// Equivalent to `SHA256(b"account:VersionedAccountHistory")[0..8]`
+pub const ACCOUNT_HISTORY_TAG: [u8; 8] = [216, 105, 27, 103, 56, 125, 50, 6];
+
+/// Equivalent to `SHA256(b"account:AccountHistory")[0..8]`. Tags accounts created before
+/// the header carried a layout version, whose byte at the offset of the version is part of
+/// a pubkey, so they are recognized by their tag and rejected as unsupported.
+pub const LEGACY_ACCOUNT_HISTORY_TAG: [u8; 8] = [242, 155, 38, 23, 9, 248, 25, 205];
+
+/// Layout version of the header, region table and schema table understood by this crate.
+/// Version 1 accounts have no schema table, and are rejected as unsupported.
+pub const ACCOUNT_HISTORY_VERSION: u8 = 2;
+
+/// Maximum number of accounts whose data can be recorded together in a single history account.
+pub const MAX_WATCHED_ACCOUNTS: usize = 4;
+
+/// Size of each field of the schema table that follows the region table.
+/// The schema is not interpreted by this crate.
+pub const SCHEMA_FIELD_SIZE: usize = 48;
+
+/// Set on the slot of tombstone elements, recorded while a watched account
+/// was too small to hold its data regions.
+pub const TOMBSTONE_FLAG: u64 = 1 << 63;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum ReaderError {
+    #[error("Account is not owned by the Account History Program")]
+    InvalidOwner,
+    #[error("Account is not a history account")]
+    InvalidAccountTag,
+    #[error("Unsupported history account layout version")]
+    UnsupportedVersion,
+    #[error("Invalid data region table")]
+    InvalidDataRegions,
+    #[error("Element size does not match the size of the requested type")]
+    ElementSizeMismatch,
+    #[error("Invalid schema table")]
+    InvalidSchema,
+    #[error("Account data is too short for the capacity of the history account")]
+    Truncated,
+    #[error("History account has a capacity of zero")]
+    InvalidCapacity,
+}
+
+impl From<ReaderError> for ProgramError {
+    fn from(e: ReaderError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+/// Mirrors the header at the start of every history account.
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct AccountHistoryHeader {
+    account_tag: [u8; 8],
+    version: u8,
+    shrink_policy: u8,
+    unchanged_policy: u8,
+    preset: u8,
+    preset_param: u32,
+    watched_accounts: [Pubkey; MAX_WATCHED_ACCOUNTS],
+    watched_account_owners: [Pubkey; MAX_WATCHED_ACCOUNTS],
+    close_authority: Pubkey,
+    pending_close_authority: Pubkey,
+    update_authority: Pubkey,
+    capacity: u32,
+    len: u32,
+    data_element_size: u32,
+    num_watched_accounts: u8,
+    has_reward_vault: u8,
+    _padding: [u8; 2],
+    num_updates: u64,
+    min_slot_delay: u32,
+    min_close_delay: u32,
+    close_initiated: Option<NonZeroU64>,
+}
+
+impl AccountHistoryHeader {
+    /// The accounts whose state is being recorded, in the order their
+    /// data regions are concatenated into each element.
+    pub fn watched_accounts(&self) -> &[Pubkey] {
+        &self.watched_accounts[..self.num_watched_accounts as usize]
+    }
+
+    /// The owner program pinned for each watched account,
+    /// or `None` where the owner is not pinned.
+    pub fn watched_account_owners(&self) -> Vec<Option<Pubkey>> {
+        self.watched_account_owners[..self.num_watched_accounts as usize]
+            .iter()
+            .map(|owner| (*owner != Pubkey::default()).then_some(*owner))
+            .collect()
+    }
+
+    /// `None` when the close authority has been renounced, meaning
+    /// the history account is permanent.
+    pub fn close_authority(&self) -> Option<Pubkey> {
+        (self.close_authority != Pubkey::default()).then_some(self.close_authority)
+    }
+
+    /// `None` when updates are permissionless.
+    pub fn update_authority(&self) -> Option<Pubkey> {
+        (self.update_authority != Pubkey::default()).then_some(self.update_authority)
+    }
+
+    /// Size of each element, including the slot.
+    pub fn data_element_size(&self) -> usize {
+        self.data_element_size as usize
+    }
+
+    /// Minimum number of slots between updates.
+    pub fn min_slot_delay(&self) -> u32 {
+        self.min_slot_delay
+    }
+
+    /// Minimum number of slots between starting and finishing the close procedure.
+    pub fn min_close_delay(&self) -> u32 {
+        self.min_close_delay
+    }
+
+    /// Whether a reward vault pays a bounty for updates of this history account.
+    pub fn has_reward_vault(&self) -> bool {
+        self.has_reward_vault != 0
+    }
+
+    /// Slot at which the close procedure was started, if it is in progress.
+    pub fn close_initiated(&self) -> Option<Slot> {
+        self.close_initiated.map(NonZeroU64::get)
+    }
+}
+
+/// Mirrors an entry of the data region table that follows the header.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C)]
+pub struct Region {
+    /// Index of the watched account on which the region is found.
+    pub account_index: u32,
+    /// Byte offset where the region begins.
+    pub offset: u32,
+    /// Length of the region in bytes.
+    pub len: u32,
+}
+
+/// A read-only view of a history account, whose elements are interpreted as `T`.
+#[derive(Debug, Clone, Copy)]
+pub struct AccountHistoryRef<'data, T: Pod> {
+    header: &'data AccountHistoryHeader,
+    regions: &'data [Region],
+    data: &'data [T],
+}
+
+impl<'data, T: Pod> AccountHistoryRef<'data, T> {
+    /// View the data of an account owned by the Account History Program.
+    pub fn from_account_data(owner: &Pubkey, data: &'data [u8]) -> Result<Self, ReaderError> {
+        if *owner != ID {
+            return Err(ReaderError::InvalidOwner);
+        }
+        Self::from_buffer(data)
+    }
+
+    /// View a history account's data, without checking its owner.
+    /// Checks the account tag, the layout version, that elements are `size_of::<T>()` bytes,
+    /// and that the data holds `capacity` of them.
+    pub fn from_buffer(data: &'data [u8]) -> Result<Self, ReaderError> {
+        if data.len() < mem::size_of::<AccountHistoryHeader>() {
+            return Err(ReaderError::InvalidAccountTag);
+        }
+        let (header, data) = data.split_at(mem::size_of::<AccountHistoryHeader>());
+        let header = bytemuck::try_from_bytes::<AccountHistoryHeader>(header)
+            .map_err(|_| ReaderError::InvalidAccountTag)?;
+        if header.account_tag == LEGACY_ACCOUNT_HISTORY_TAG {
+            return Err(ReaderError::UnsupportedVersion);
+        }
+        if header.account_tag != ACCOUNT_HISTORY_TAG {
+            return Err(ReaderError::InvalidAccountTag);
+        }
+        if header.version != ACCOUNT_HISTORY_VERSION {
+            return Err(ReaderError::UnsupportedVersion);
+        }
+        if header.data_element_size as usize != mem::size_of::<T>() {
+            return Err(ReaderError::ElementSizeMismatch);
+        }
+        if header.capacity == 0 {
+            return Err(ReaderError::InvalidCapacity);
+        }
+        // The region table is a u32 count followed by the regions, padded to 8 bytes
+        if data.len() < mem::size_of::<u32>() {
+            return Err(ReaderError::InvalidDataRegions);
+        }
+        let num_regions = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+        let table_size = (mem::size_of::<u32>() + num_regions * mem::size_of::<Region>() + 7) & !7;
+        if data.len() < table_size {
+            return Err(ReaderError::InvalidDataRegions);
+        }
+        let (table, data) = data.split_at(table_size);
+        let regions = bytemuck::try_cast_slice(&table[4..][..num_regions * mem::size_of::<Region>()])
+            .map_err(|_| ReaderError::InvalidDataRegions)?;
+        // The schema table is a u32 count padded to 8 bytes, followed by 48 byte fields
+        if data.len() < mem::size_of::<u64>() {
+            return Err(ReaderError::InvalidSchema);
+        }
+        let num_fields = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+        let schema_size = mem::size_of::<u64>() + num_fields * SCHEMA_FIELD_SIZE;
+        if data.len() < schema_size {
+            return Err(ReaderError::InvalidSchema);
+        }
+        let data = &data[schema_size..];
+        let elements_size = header.capacity as usize * mem::size_of::<T>();
+        if data.len() < elements_size {
+            return Err(ReaderError::Truncated);
+        }
+        let data = bytemuck::try_cast_slice::<_, T>(&data[..elements_size])
+            .map_err(|_| ReaderError::ElementSizeMismatch)?;
+        Ok(Self { header, regions, data })
+    }
+
+    pub fn header(&self) -> &'data AccountHistoryHeader {
+        self.header
+    }
+
+    /// Locations of the account data recorded on each element, in the order they are stored.
+    pub fn data_regions(&self) -> &'data [Region] {
+        self.regions
+    }
+
+    /// Most recently modified index. Returns zero when there is no data.
+    pub fn most_recent_index(&self) -> usize {
+        self.header.num_updates as usize % self.header.capacity as usize
+    }
+
+    /// Most recently added value.
+    pub fn most_recent_entry(&self) -> &'data T {
+        &self.data[self.most_recent_index()]
+    }
+
+    /// The `n`th most recent value, where zero is the most recent.
+    pub fn get(&self, n: usize) -> Option<&'data T> {
+        if n >= self.len() {
+            return None;
+        }
+        let index = (self.most_recent_index() + self.capacity() - n) % self.capacity();
+        Some(&self.data[index])
+    }
+
+    /// Iterates from newest value to oldest.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &'data T> + '_ {
+        (0..self.len()).map(|n| self.get(n).unwrap())
+    }
+
+    /// Total number of element updates that have taken place on this history account.
+    pub fn num_updates(&self) -> usize {
+        self.header.num_updates as usize
+    }
+
+    /// All the accounts whose state is being recorded on this history account.
+    pub fn watched_accounts(&self) -> &'data [Pubkey] {
+        self.header.watched_accounts()
+    }
+
+    /// Maximum number of elements supported by this account.
+    pub fn capacity(&self) -> usize {
+        self.header.capacity as usize
+    }
+
+    /// The number of values indexed so far. Ranges from 0 to `self.capacity()`.
+    pub fn len(&self) -> usize {
+        self.header.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'data, T: Pod> Index<usize> for AccountHistoryRef<'data, T> {
+    type Output = T;
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.data[index % self.capacity()]
+    }
+}
+
+/// The slot at which an element was recorded, without the `TOMBSTONE_FLAG`.
+/// Assumes the element starts with its slot, as every element does.
+pub fn entry_slot<T: Pod>(entry: &T) -> Slot {
+    bytemuck::pod_read_unaligned::<Slot>(&bytemuck::bytes_of(entry)[..8]) & !TOMBSTONE_FLAG
+}
+
+/// Whether an element is a tombstone, with zero-filled data.
+pub fn is_tombstone<T: Pod>(entry: &T) -> bool {
+    bytemuck::pod_read_unaligned::<Slot>(&bytemuck::bytes_of(entry)[..8]) & TOMBSTONE_FLAG != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, Pod, Zeroable, PartialEq)]
+    #[repr(C)]
+    struct Balance {
+        slot: Slot,
+        amount: u64,
+    }
+
+    fn mock_account(capacity: u32, amounts: &[u64]) -> Vec<u8> {
+        let header = AccountHistoryHeader {
+            account_tag: ACCOUNT_HISTORY_TAG,
+            version: ACCOUNT_HISTORY_VERSION,
+            capacity,
+            len: amounts.len().min(capacity as usize) as u32,
+            data_element_size: 16,
+            num_watched_accounts: 1,
+            num_updates: amounts.len() as u64,
+            ..Zeroable::zeroed()
+        };
+        let mut table = vec![0u8; 16];
+        table[..4].copy_from_slice(&1u32.to_le_bytes());
+        table[4..16].copy_from_slice(bytemuck::bytes_of(&Region { account_index: 0, offset: 64, len: 8 }));
+        let mut elements = vec![Balance::zeroed(); capacity as usize];
+        // As in the program, the k-th push (counting from 0, the push at initialization included)
+        // writes at `(k + 1) % capacity`, leaving `num_updates` equal to the number of pushes
+        for (i, amount) in amounts.iter().enumerate() {
+            elements[(i + 1) % capacity as usize] = Balance { slot: i as u64, amount: *amount };
+        }
+        // Followed by an empty schema table
+        let schema = [0u8; 8];
+        [bytemuck::bytes_of(&header), &table, &schema, bytemuck::cast_slice(&elements)].concat()
+    }
+
+    /// Copy into a u64 allocation to get an 8-byte aligned buffer, as account data is.
+    fn align(bytes: &[u8]) -> Vec<u64> {
+        let mut aligned = vec![0u64; bytes.len() / 8];
+        bytemuck::cast_slice_mut(&mut aligned).copy_from_slice(bytes);
+        aligned
+    }
+
+    #[test]
+    fn read() {
+        let aligned = align(&mock_account(3, &[10, 20, 30, 40]));
+        let data: &[u8] = bytemuck::cast_slice(&aligned);
+
+        let history = AccountHistoryRef::<Balance>::from_account_data(&ID, data).unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.most_recent_entry().amount, 40);
+        assert_eq!(history.iter().map(|b| b.amount).collect::<Vec<_>>(), vec![40, 30, 20]);
+        assert_eq!(history.get(2).unwrap().slot, 1);
+        assert!(history.get(3).is_none());
+        assert_eq!(history.data_regions(), &[Region { account_index: 0, offset: 64, len: 8 }]);
+        assert_eq!(entry_slot(history.most_recent_entry()), 3);
+
+        assert_eq!(
+            AccountHistoryRef::<Balance>::from_account_data(&Pubkey::new_unique(), data).unwrap_err(),
+            ReaderError::InvalidOwner,
+        );
+        assert_eq!(
+            AccountHistoryRef::<[u64; 3]>::from_buffer(data).unwrap_err(),
+            ReaderError::ElementSizeMismatch,
+        );
+        assert_eq!(
+            AccountHistoryRef::<Balance>::from_buffer(&data[8..]).unwrap_err(),
+            ReaderError::InvalidAccountTag,
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let aligned = align(&mock_account(3, &[10, 20, 30, 40]));
+        let data: &[u8] = bytemuck::cast_slice(&aligned);
+        assert_eq!(
+            AccountHistoryRef::<Balance>::from_buffer(&data[..data.len() - 8]).unwrap_err(),
+            ReaderError::Truncated,
+        );
+
+        let mut aligned = align(&mock_account(3, &[10]));
+        let bytes: &mut [u8] = bytemuck::cast_slice_mut(&mut aligned);
+        bytemuck::from_bytes_mut::<AccountHistoryHeader>(&mut bytes[..mem::size_of::<AccountHistoryHeader>()]).capacity = 0;
+        assert_eq!(
+            AccountHistoryRef::<Balance>::from_buffer(bytes).unwrap_err(),
+            ReaderError::InvalidCapacity,
+        );
+
+        // Accounts created before the header was versioned
+        let mut aligned = align(&mock_account(3, &[10]));
+        let bytes: &mut [u8] = bytemuck::cast_slice_mut(&mut aligned);
+        bytes[..8].copy_from_slice(&LEGACY_ACCOUNT_HISTORY_TAG);
+        assert_eq!(
+            AccountHistoryRef::<Balance>::from_buffer(bytes).unwrap_err(),
+            ReaderError::UnsupportedVersion,
+        );
+
+        // Accounts created before the schema table was added
+        bytes[..8].copy_from_slice(&ACCOUNT_HISTORY_TAG);
+        bytemuck::from_bytes_mut::<AccountHistoryHeader>(&mut bytes[..mem::size_of::<AccountHistoryHeader>()]).version = 1;
+        assert_eq!(
+            AccountHistoryRef::<Balance>::from_buffer(bytes).unwrap_err(),
+            ReaderError::UnsupportedVersion,
+        );
+    }
+}